@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::arrangement::record_placement;
+use crate::bus::{self, Event, ZoneSpan};
+use crate::common::{
+    enumerate_monitors, focus_window, get_foreground_window, nav_window_list, next_window,
+    order_window_list, previous_window, OrderingDirection,
+};
+use crate::grid::Grid;
+use crate::profile::current_profile;
+use crate::CONFIG;
+
+/// Something a key binding can do. Arguments are read from the same table as the
+/// action name, e.g. `{ key = "ALT+H", action = "focus", direction = "left" }`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum Action {
+    /// Opens the grid, switching to `profile` first if given
+    OpenGrid {
+        profile: Option<String>,
+    },
+    /// Opens the grid and closes it again after one resize
+    QuickResize {
+        profile: Option<String>,
+    },
+    /// Maximizes the active window, or restores it to where it was before
+    Maximize,
+    /// Focuses the nearest window in `direction`
+    Focus {
+        direction: Direction,
+    },
+    /// Moves the foreground window to a zone of the current grid. Rows, columns and
+    /// monitors count from 0, the spans default to a single zone, and the monitor
+    /// defaults to the one under the cursor.
+    MoveToZone {
+        row: usize,
+        column: usize,
+        row_span: Option<usize>,
+        column_span: Option<usize>,
+        monitor: Option<usize>,
+    },
+    SwitchProfile {
+        profile: String,
+    },
+    /// Remembers where every open window is, under `name`
+    SaveSnapshot {
+        name: String,
+    },
+    /// Moves open windows back to where they were in the snapshot `name`
+    RestoreSnapshot {
+        name: String,
+    },
+    /// Calls `function` defined in one of the scripts in the config directory
+    Script {
+        function: String,
+    },
+    /// Waits for one of the `[leader]` sequences to be typed
+    Leader,
+    Exit,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::OpenGrid { profile: None } => write!(f, "Open grid"),
+            Action::OpenGrid {
+                profile: Some(profile),
+            } => write!(f, "Open grid in {}", profile),
+            Action::QuickResize { profile: None } => write!(f, "Quick resize"),
+            Action::QuickResize {
+                profile: Some(profile),
+            } => write!(f, "Quick resize in {}", profile),
+            Action::Maximize => write!(f, "Maximize / restore"),
+            Action::Focus { direction } => write!(f, "Focus {:?}", direction),
+            Action::MoveToZone { row, column, .. } => {
+                write!(f, "Move to row {}, column {}", row, column)
+            }
+            Action::SwitchProfile { profile } => write!(f, "Switch to {}", profile),
+            Action::SaveSnapshot { name } => write!(f, "Save snapshot {}", name),
+            Action::RestoreSnapshot { name } => write!(f, "Restore snapshot {}", name),
+            Action::Script { function } => write!(f, "Run script {}", function),
+            Action::Leader => write!(f, "Leader"),
+            Action::Exit => write!(f, "Exit"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+pub fn focus(direction: Direction) {
+    let mut windows = nav_window_list().expect("failed nav_window_list");
+    if windows.is_empty() {
+        return;
+    }
+
+    let ordering = match direction {
+        Direction::Left | Direction::Right => OrderingDirection::Horizontal,
+        Direction::Up | Direction::Down => OrderingDirection::Vertical,
+    };
+    order_window_list(&mut windows, ordering);
+
+    let window = match direction {
+        Direction::Left | Direction::Up => previous_window(&windows),
+        Direction::Right | Direction::Down => next_window(&windows),
+    };
+
+    if let Some(hwnd) = window {
+        focus_window(*hwnd);
+    }
+}
+
+pub fn move_to_zone(
+    row: usize,
+    column: usize,
+    row_span: usize,
+    column_span: usize,
+    monitor: Option<usize>,
+) {
+    let grid = match zone_grid(monitor) {
+        Some(grid) => grid,
+        None => return,
+    };
+
+    let to = (row + row_span.max(1) - 1, column + column_span.max(1) - 1);
+
+    let mut rect = match grid.span_area((row, column), to) {
+        Some(rect) => rect,
+        None => {
+            log::warn!(
+                "Zone at row {}, column {} is outside the current grid",
+                row,
+                column
+            );
+            return;
+        }
+    };
+
+    let mut window = get_foreground_window();
+
+    window.restore();
+    rect.adjust_for_border(window.transparent_border());
+    window.set_pos(rect, None);
+
+    record_placement(&current_profile(), window, window.rect());
+    bus::publish(Event::window_placed(
+        window,
+        Some(ZoneSpan {
+            from: (row, column),
+            to,
+        }),
+    ));
+}
+
+/// Grid of monitor `monitor`, or of the monitor under the cursor
+fn zone_grid(monitor: Option<usize>) -> Option<Grid> {
+    // Building a grid resolves the active profile, which locks CONFIG the first time,
+    // as it is when `grout apply` runs without an instance
+    let config = CONFIG.lock().unwrap().clone();
+
+    match monitor {
+        Some(index) => match enumerate_monitors().get(index) {
+            Some(monitor) => Some(Grid::for_monitor(&config, monitor)),
+            None => {
+                log::warn!("No monitor {} to move to", index);
+                None
+            }
+        },
+        None => Some(Grid::from(&config)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn zone_grid_before_profile_is_loaded() {
+        let (sender, receiver) = mpsc::channel();
+
+        // Nothing has touched the active profile yet, like a command run without an
+        // instance
+        thread::spawn(move || {
+            let _ = sender.send(zone_grid(None).is_some());
+        });
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(true));
+    }
+}
@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+use crate::cache::{cache_file, write_atomic};
+use crate::common::{nav_window_list, window_exe, Rect};
+use crate::window::Window;
+
+/// Where a window of an application was last placed
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Placement {
+    exe: String,
+    rect: Rect,
+}
+
+/// Window placements made with each profile, keyed by profile name
+pub type Arrangements = HashMap<String, Vec<Placement>>;
+
+pub trait ArrangementCache {
+    fn load() -> Arrangements;
+    fn save(&self);
+}
+
+impl ArrangementCache for Arrangements {
+    fn load() -> Arrangements {
+        if let Ok(path) = cache_file("arrangements.ron") {
+            if let Ok(contents) = fs::read_to_string(&path) {
+                match ron::de::from_str(&contents) {
+                    Ok(arrangements) => return arrangements,
+                    Err(e) => log::warn!("Failed to load {}: {}", path.display(), e),
+                }
+            }
+        }
+
+        HashMap::new()
+    }
+
+    fn save(&self) {
+        let result = cache_file("arrangements.ron").and_then(|path| {
+            let serialized = ron::ser::to_string(&self)?;
+            write_atomic(&path, &serialized)
+        });
+
+        if let Err(e) = result {
+            log::warn!("Failed to save arrangements: {}", e);
+        }
+    }
+}
+
+/// Records that `window` was placed at `rect` while `profile` was active
+pub fn record_placement(profile: &str, window: Window, rect: Rect) {
+    let exe = unsafe { window_exe(window.0) };
+    if exe.is_empty() {
+        return;
+    }
+
+    let mut arrangements = Arrangements::load();
+    let placements = arrangements.entry(profile.to_owned()).or_default();
+
+    placements.retain(|placement| placement.exe != exe);
+    placements.push(Placement { exe, rect });
+
+    arrangements.save();
+}
+
+/// Moves open windows back to where they were last placed with `profile`
+pub fn restore_arrangement(profile: &str) {
+    let arrangements = Arrangements::load();
+
+    if let Some(placements) = arrangements.get(profile) {
+        restore_placements(placements);
+    }
+}
+
+/// Saves where every open window is as the snapshot `name`, replacing any snapshot
+/// of that name. Returns the number of windows saved.
+pub fn save_snapshot(name: &str) -> usize {
+    let placements = nav_window_list()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|hwnd| {
+            let exe = unsafe { window_exe(hwnd) };
+            if exe.is_empty() {
+                return None;
+            }
+
+            Some(Placement {
+                exe,
+                rect: Window(hwnd).rect(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let count = placements.len();
+
+    let mut snapshots = load_snapshots();
+    snapshots.insert(name.to_owned(), placements);
+    save_snapshots(&snapshots);
+
+    count
+}
+
+/// Moves open windows back to where they were in the snapshot `name`. Returns
+/// false if there's no such snapshot.
+pub fn restore_snapshot(name: &str) -> bool {
+    match load_snapshots().get(name) {
+        Some(placements) => {
+            restore_placements(placements);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Snapshots use the same format as arrangements, keyed by snapshot name
+fn load_snapshots() -> Arrangements {
+    if let Ok(path) = cache_file("snapshots.ron") {
+        if let Ok(contents) = fs::read_to_string(&path) {
+            match ron::de::from_str(&contents) {
+                Ok(snapshots) => return snapshots,
+                Err(e) => log::warn!("Failed to load {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    HashMap::new()
+}
+
+fn save_snapshots(snapshots: &Arrangements) {
+    let result = cache_file("snapshots.ron").and_then(|path| {
+        let serialized = ron::ser::to_string(snapshots)?;
+        write_atomic(&path, &serialized)
+    });
+
+    if let Err(e) = result {
+        log::warn!("Failed to save snapshots: {}", e);
+    }
+}
+
+fn restore_placements(placements: &[Placement]) {
+    let windows = nav_window_list().unwrap_or_default();
+
+    for hwnd in windows {
+        let exe = unsafe { window_exe(hwnd) };
+
+        if let Some(placement) = placements.iter().find(|placement| placement.exe == exe) {
+            let mut window = Window(hwnd);
+
+            window.restore();
+            window.set_pos(placement.rect, None);
+        }
+    }
+}
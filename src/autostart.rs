@@ -1,4 +1,3 @@
-use anyhow::format_err;
 use std::env;
 use std::fs;
 use std::mem;
@@ -13,12 +12,11 @@ use windows::{
     },
 };
 
+use crate::config::{config_dir, config_path, is_custom_location};
 use crate::Result;
 
 pub unsafe fn toggle_autostart_registry_key(enabled: bool) -> Result<()> {
-    let mut app_path =
-        dirs::config_dir().ok_or_else(|| format_err!("Failed to get config directory"))?;
-    app_path.push("grout");
+    let mut app_path = config_dir()?;
     app_path.push("grout.exe");
 
     let current_path = env::current_exe()?;
@@ -26,7 +24,16 @@ pub unsafe fn toggle_autostart_registry_key(enabled: bool) -> Result<()> {
         fs::copy(current_path, &app_path)?;
     }
 
-    let app_path = app_path.to_str().unwrap_or_default();
+    // The copy can't find a custom config location on its own
+    let command = if is_custom_location() {
+        format!(
+            "\"{}\" --config \"{}\"",
+            app_path.display(),
+            config_path()?.display()
+        )
+    } else {
+        app_path.to_str().unwrap_or_default().to_owned()
+    };
     let key_name = w!("Software\\Microsoft\\Windows\\CurrentVersion\\Run");
     let value_name = w!("grout");
 
@@ -45,7 +52,7 @@ pub unsafe fn toggle_autostart_registry_key(enabled: bool) -> Result<()> {
             None,
         ) == WIN32_ERROR(0)
         {
-            let _ = RegSetValueExW(key, value_name, 0, REG_SZ, Some(app_path.as_bytes()));
+            let _ = RegSetValueExW(key, value_name, 0, REG_SZ, Some(command.as_bytes()));
         }
     } else {
         let _ = RegDeleteKeyValueW(HKEY_CURRENT_USER, key_name, value_name);
@@ -0,0 +1,139 @@
+use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
+use serde::Serialize;
+use std::sync::Mutex;
+
+use crate::common::{
+    get_active_monitor, monitor_of_window, window_exe, window_title, Monitor, Rect,
+};
+use crate::profile::current_profile;
+use crate::window::Window;
+
+/// Events queued for a subscriber that isn't keeping up are dropped past this
+const SUBSCRIBER_QUEUE: usize = 64;
+
+static SUBSCRIBERS: Mutex<Vec<Sender<Event>>> = Mutex::new(Vec::new());
+
+/// Something that happened, for observers outside the message loop such as IPC
+/// subscribers
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    ProfileChanged {
+        profile: String,
+    },
+    GridOpened {
+        profile: String,
+        monitor: Monitor,
+    },
+    GridClosed,
+    /// A window was moved by the grid, an action or the maximize toggle. `zone` is
+    /// the span of grid zones it was moved to, if any.
+    WindowPlaced {
+        window: WindowInfo,
+        zone: Option<ZoneSpan>,
+        monitor: Option<Monitor>,
+    },
+    FocusChanged {
+        window: WindowInfo,
+    },
+    /// A top level window was opened, or shown again after being hidden
+    WindowCreated {
+        window: WindowInfo,
+    },
+    /// The cursor moved to another monitor while the grid was open
+    MonitorChanged {
+        monitor: Monitor,
+    },
+    /// Monitors were connected, disconnected or rearranged
+    DisplayChanged {
+        monitors: Vec<Monitor>,
+    },
+}
+
+/// Names of the events, as used in `Event::name`
+pub const EVENT_NAMES: &[&str] = &[
+    "profile-changed",
+    "grid-opened",
+    "grid-closed",
+    "window-placed",
+    "focus-changed",
+    "window-created",
+    "monitor-changed",
+    "display-changed",
+];
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::ProfileChanged { .. } => "profile-changed",
+            Event::GridOpened { .. } => "grid-opened",
+            Event::GridClosed => "grid-closed",
+            Event::WindowPlaced { .. } => "window-placed",
+            Event::FocusChanged { .. } => "focus-changed",
+            Event::WindowCreated { .. } => "window-created",
+            Event::MonitorChanged { .. } => "monitor-changed",
+            Event::DisplayChanged { .. } => "display-changed",
+        }
+    }
+
+    pub fn grid_opened() -> Self {
+        Event::GridOpened {
+            profile: current_profile(),
+            monitor: unsafe { get_active_monitor() },
+        }
+    }
+
+    pub fn window_placed(window: Window, zone: Option<ZoneSpan>) -> Self {
+        Event::WindowPlaced {
+            window: WindowInfo::from(window),
+            zone,
+            monitor: unsafe { monitor_of_window(window.0) },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowInfo {
+    pub exe: String,
+    pub title: String,
+    pub rect: Rect,
+}
+
+impl From<Window> for WindowInfo {
+    fn from(window: Window) -> Self {
+        WindowInfo {
+            exe: unsafe { window_exe(window.0) },
+            title: unsafe { window_title(window.0) },
+            rect: window.rect(),
+        }
+    }
+}
+
+/// First and last grid zone covered, each as (row, column) counted from 0
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ZoneSpan {
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+}
+
+/// Sends `event` to every subscriber, forgetting those that have gone away
+pub fn publish(event: Event) {
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+
+    subscribers.retain(|subscriber| match subscriber.try_send(event.clone()) {
+        Ok(()) => true,
+        Err(TrySendError::Full(_)) => {
+            log::debug!("Dropped {} event for a slow subscriber", event.name());
+            true
+        }
+        Err(TrySendError::Disconnected(_)) => false,
+    });
+}
+
+/// Receives every event published from now on. Dropping the receiver
+/// unsubscribes.
+pub fn subscribe() -> Receiver<Event> {
+    let (sender, receiver) = bounded(SUBSCRIBER_QUEUE);
+    SUBSCRIBERS.lock().unwrap().push(sender);
+    receiver
+}
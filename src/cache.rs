@@ -0,0 +1,208 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::config::{config_dir, Config};
+use crate::grid::{unix_time, GridCache, GridConfigKey, GridConfigs};
+use crate::Result;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Directory holding cached state, created if it doesn't exist
+pub fn cache_dir() -> Result<PathBuf> {
+    let mut cache_path = config_dir()?;
+    cache_path.push("cache");
+
+    if !cache_path.exists() {
+        fs::create_dir_all(&cache_path)?;
+    }
+
+    Ok(cache_path)
+}
+
+/// Path of a file in the cache directory
+pub fn cache_file(name: &str) -> Result<PathBuf> {
+    let mut cache_path = cache_dir()?;
+    cache_path.push(name);
+    Ok(cache_path)
+}
+
+/// Backup of `path`, holding the last contents that were successfully replaced
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".bak");
+    path.with_file_name(file_name)
+}
+
+/// Writes `contents` to a temporary file, then renames it over `path`, so readers
+/// never see a partially written file. The previous file is kept as a backup, and
+/// `path` is left as it was if the backup can't be made.
+pub fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(file_name);
+
+    {
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+    }
+
+    let backed_up = if path.exists() {
+        fs::copy(path, backup_path(path)).map(|_| ())
+    } else {
+        Ok(())
+    };
+    let replaced = backed_up.and_then(|()| fs::rename(&temp_path, path));
+
+    if let Err(e) = replaced {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+/// Human readable listing of the grid cache, most recently used first
+pub fn list_grid_cache() -> String {
+    let configs = GridConfigs::load();
+    if configs.is_empty() {
+        return "Grid cache is empty".to_owned();
+    }
+
+    let mut entries = configs.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(_, grid_config)| std::cmp::Reverse(grid_config.last_used()));
+
+    let now = unix_time();
+    entries
+        .into_iter()
+        .map(|(key, grid_config)| {
+            let last_used = if grid_config.last_used() == 0 {
+                "never".to_owned()
+            } else {
+                match (now.saturating_sub(grid_config.last_used())) / SECONDS_PER_DAY {
+                    0 => "today".to_owned(),
+                    1 => "1 day ago".to_owned(),
+                    days => format!("{} days ago", days),
+                }
+            };
+
+            format!(
+                "{} | {} | {}x{} | last used {}",
+                key.monitor(),
+                key.profile(),
+                grid_config.rows(),
+                grid_config.columns(),
+                last_used
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Removes the cached dimensions for one monitor and profile. Returns false if
+/// there was no such entry.
+pub fn reset_grid_cache_entry(monitor: &str, profile: &str) -> bool {
+    let mut configs = GridConfigs::load();
+
+    let key = configs
+        .keys()
+        .find(|key| key.monitor() == monitor && key.profile() == profile)
+        .cloned();
+
+    match key {
+        Some(key) => {
+            configs.remove(&key);
+            configs.save();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Removes the cached dimensions for the monitor under the cursor and the current profile
+pub fn reset_active_grid_cache_entry(config: &Config) -> bool {
+    let key = GridConfigKey::new(config);
+
+    reset_grid_cache_entry(key.monitor(), key.profile())
+}
+
+/// Removes entries not used in the last `days` days. Returns how many were removed.
+pub fn prune_grid_cache(days: u64) -> usize {
+    let mut configs = GridConfigs::load();
+    let cutoff = unix_time().saturating_sub(days * SECONDS_PER_DAY);
+
+    let before = configs.len();
+    configs.retain(|_, grid_config| grid_config.last_used() >= cutoff);
+    let removed = before - configs.len();
+
+    if removed > 0 {
+        configs.save();
+    }
+
+    removed
+}
+
+pub fn export_grid_cache(path: &Path) -> Result<()> {
+    GridConfigs::load().save_to(path)
+}
+
+/// Merges the entries of an exported grid cache into the current one, replacing
+/// entries with the same key. Returns how many entries were imported.
+pub fn import_grid_cache(path: &Path) -> Result<usize> {
+    let (imported, _) = GridConfigs::load_from(path)?;
+    let count = imported.len();
+
+    let mut configs = GridConfigs::load();
+    configs.extend(imported);
+    configs.save();
+
+    Ok(count)
+}
+
+/// Default location for exports made from the system tray
+pub fn default_export_path() -> Result<PathBuf> {
+    let mut export_path = config_dir()?;
+    export_path.push("grid-export.ron");
+    Ok(export_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for each test, so tests can run in parallel
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("grout-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn previous_contents_are_backed_up() {
+        let path = temp_dir("backup").join("grid.ron");
+
+        write_atomic(&path, "first").unwrap();
+        write_atomic(&path, "second").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(fs::read_to_string(backup_path(&path)).unwrap(), "first");
+    }
+
+    #[test]
+    fn failed_backup_is_reported() {
+        let dir = temp_dir("failed-backup");
+        let path = dir.join("grid.ron");
+
+        write_atomic(&path, "first").unwrap();
+        // A directory in the way of the backup stops it being copied
+        fs::create_dir(backup_path(&path)).unwrap();
+
+        assert!(write_atomic(&path, "second").is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first");
+
+        let files = fs::read_dir(&dir).unwrap().count();
+        assert_eq!(files, 2, "temporary file was left behind");
+    }
+}
@@ -0,0 +1,312 @@
+use std::path::{Path, PathBuf};
+
+use crate::action::{self, Action};
+use crate::arrangement;
+use crate::cache;
+use crate::common::attach_console;
+use crate::config;
+use crate::ipc::{self, Command};
+use crate::profile;
+use crate::settings;
+
+const USAGE: &str = "Usage:
+    grout [--config <path>] [command]
+    grout                               Run in the background
+    grout --replace                     Run in the background, closing the running
+                                        instance first
+    grout apply --zone <row>,<column>[:<row>,<column>] [--monitor <index>]
+                                        Move the active window to a zone, or the span
+                                        of zones between two, counted from 0
+    grout profile set <profile>         Switch to a profile
+    grout snapshot save <name>          Remember where every open window is
+    grout snapshot restore <name>       Move windows back to where they were in a snapshot
+    grout cache list                    List cached grid dimensions
+    grout cache reset <monitor> <profile>
+                                        Remove the cached grid for a monitor and profile
+    grout cache prune <days>            Remove cached grids not used in <days> days
+    grout cache export <path>           Export the grid cache to a file
+    grout cache import <path>           Merge an exported grid cache into the cache
+    grout config set <key> <value>      Set a config key, e.g. grid.tile_width 4
+    grout config unset <key>            Remove a config key so its default is used
+    grout config toggle <key>           Flip a true / false config key
+    grout config show                   Print the config merged from all files and
+                                        environment variables
+    grout config check                  Check the config for problems
+    grout reload                        Reload the config of the running instance
+    grout open config                   Open the config file from the running instance
+    grout quit                          Close the running instance
+
+apply, profile and snapshot commands run in the instance running in the
+background if there is one, and directly otherwise. Only one instance runs at
+a time, starting grout again while it's running does nothing.";
+
+/// Removes options that apply to every command, such as `--config <path>`, from
+/// `args` and applies them
+pub fn take_global_options(args: Vec<String>) -> Vec<String> {
+    let mut remaining = vec![];
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        if let Some(path) = arg.strip_prefix("--config=") {
+            config::set_config_path(PathBuf::from(path));
+        } else if arg == "--config" {
+            match args.next() {
+                Some(path) => config::set_config_path(PathBuf::from(path)),
+                // Left in place so it's reported as an unknown command
+                None => remaining.push(arg),
+            }
+        } else {
+            remaining.push(arg);
+        }
+    }
+
+    remaining
+}
+
+/// Removes `--replace` from `args`, returning whether it was given
+pub fn take_replace_flag(args: &mut Vec<String>) -> bool {
+    let count = args.len();
+    args.retain(|arg| arg != "--replace");
+    args.len() != count
+}
+
+/// Runs the command given on the command line. Returns the exit code, or `None`
+/// if no command was given and the program should start normally.
+pub fn run(args: &[String]) -> Option<i32> {
+    if args.is_empty() {
+        return None;
+    }
+
+    attach_console();
+
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    let result = match args.as_slice() {
+        ["apply", options @ ..] => run_apply_command(options),
+        ["profile", "set", profile] => run_profile_command(profile),
+        ["snapshot", command @ ..] => run_snapshot_command(command),
+        ["cache", command @ ..] => run_cache_command(command),
+        ["config", command @ ..] => run_config_command(command),
+        ["reload"] => forward(Command::ReloadConfig),
+        ["open", "config"] => forward(Command::OpenConfig),
+        ["quit"] => forward(Command::Quit),
+        ["help"] | ["--help"] | ["-h"] => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("Unknown command: {}", args.join(" "))),
+    };
+
+    match result {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            Some(1)
+        }
+    }
+}
+
+/// Runs `action` in the instance running in the background, or with
+/// `run_directly` if there isn't one
+fn dispatch<F>(action: Action, run_directly: F) -> Result<(), String>
+where
+    F: FnOnce() -> Result<(), String>,
+{
+    match ipc::send(Command::Action(action.clone())) {
+        Ok(true) => {
+            println!("{} (sent to the running instance)", action);
+            Ok(())
+        }
+        Ok(false) => run_directly(),
+        Err(e) => Err(format!("Failed to send to the running instance: {}", e)),
+    }
+}
+
+/// Runs a command that only applies to the instance running in the background
+fn forward(command: Command) -> Result<(), String> {
+    match ipc::send(command) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err("grout isn't running".to_string()),
+        Err(e) => Err(format!("Failed to send to the running instance: {}", e)),
+    }
+}
+
+fn run_apply_command(args: &[&str]) -> Result<(), String> {
+    let mut zone = None;
+    let mut monitor = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}", arg))?;
+
+        match *arg {
+            "--zone" => zone = Some(parse_zone(value)?),
+            "--monitor" => {
+                let index = value
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid monitor index: {}", value))?;
+                monitor = Some(index);
+            }
+            _ => return Err(format!("Unknown apply option: {}", arg)),
+        }
+    }
+
+    let ((row, column), (to_row, to_column)) =
+        zone.ok_or_else(|| "apply needs --zone".to_string())?;
+
+    let action = Action::MoveToZone {
+        row,
+        column,
+        row_span: Some(to_row - row + 1),
+        column_span: Some(to_column - column + 1),
+        monitor,
+    };
+
+    dispatch(action, || {
+        action::move_to_zone(
+            row,
+            column,
+            to_row - row + 1,
+            to_column - column + 1,
+            monitor,
+        );
+        Ok(())
+    })
+}
+
+/// A zone as (row, column)
+type Zone = (usize, usize);
+
+/// Parses `row,column` or a span `row,column:row,column`, returning the first and
+/// last zone
+fn parse_zone(zone: &str) -> Result<(Zone, Zone), String> {
+    let parse_position = |position: &str| {
+        let (row, column) = position.split_once(',')?;
+        Some((row.trim().parse().ok()?, column.trim().parse().ok()?))
+    };
+
+    let (from, to) = match zone.split_once(':') {
+        Some((from, to)) => (parse_position(from), parse_position(to)),
+        None => (parse_position(zone), parse_position(zone)),
+    };
+
+    match (from, to) {
+        (Some(from), Some(to)) if from.0 <= to.0 && from.1 <= to.1 => Ok((from, to)),
+        _ => Err(format!("Invalid zone: {}", zone)),
+    }
+}
+
+fn run_profile_command(profile: &str) -> Result<(), String> {
+    let config = config::read_config().map_err(|e| format!("Failed to load config:\n{}", e))?;
+    if config.profile(profile).is_none() {
+        return Err(format!("No profile named {}", profile));
+    }
+
+    let action = Action::SwitchProfile {
+        profile: profile.to_owned(),
+    };
+
+    dispatch(action, || {
+        profile::remember_profile(&config, profile);
+        println!("{} will be active when grout starts", profile);
+        Ok(())
+    })
+}
+
+fn run_snapshot_command(args: &[&str]) -> Result<(), String> {
+    match args {
+        ["save", name] => {
+            let action = Action::SaveSnapshot {
+                name: name.to_string(),
+            };
+
+            dispatch(action, || {
+                let count = arrangement::save_snapshot(name);
+                println!("Saved {} windows as {}", count, name);
+                Ok(())
+            })
+        }
+        ["restore", name] => {
+            let action = Action::RestoreSnapshot {
+                name: name.to_string(),
+            };
+
+            dispatch(action, || {
+                if !arrangement::restore_snapshot(name) {
+                    return Err(format!("No snapshot named {}", name));
+                }
+                println!("Restored {}", name);
+                Ok(())
+            })
+        }
+        _ => Err(format!("Unknown snapshot command: {}", args.join(" "))),
+    }
+}
+
+fn run_cache_command(args: &[&str]) -> Result<(), String> {
+    match args {
+        ["list"] => {
+            println!("{}", cache::list_grid_cache());
+        }
+        ["reset", monitor, profile] => {
+            if cache::reset_grid_cache_entry(monitor, profile) {
+                println!("Removed cached grid for {} | {}", monitor, profile);
+            } else {
+                return Err(format!("No cached grid for {} | {}", monitor, profile));
+            }
+        }
+        ["prune", days] => {
+            let days = days
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid number of days: {}", days))?;
+
+            let removed = cache::prune_grid_cache(days);
+            println!("Removed {} cached grids", removed);
+        }
+        ["export", path] => {
+            cache::export_grid_cache(Path::new(path))
+                .map_err(|e| format!("Failed to export grid cache: {}", e))?;
+            println!("Exported grid cache to {}", path);
+        }
+        ["import", path] => {
+            let count = cache::import_grid_cache(Path::new(path))
+                .map_err(|e| format!("Failed to import grid cache: {}", e))?;
+            println!("Imported {} cached grids", count);
+        }
+        _ => return Err(format!("Unknown cache command: {}", args.join(" "))),
+    }
+
+    Ok(())
+}
+
+fn run_config_command(args: &[&str]) -> Result<(), String> {
+    match args {
+        ["set", key, value] => {
+            settings::set(key, value).map_err(|e| format!("Failed to set {}:\n{}", key, e))?;
+            println!("Set {} = {}", key, value);
+        }
+        ["unset", key] => {
+            settings::unset(key).map_err(|e| format!("Failed to unset {}:\n{}", key, e))?;
+            println!("Unset {}", key);
+        }
+        ["check"] => {
+            config::read_config().map_err(|e| format!("Config has problems:\n{}", e))?;
+            println!("Config is valid");
+        }
+        ["show"] => {
+            let config =
+                config::effective_config().map_err(|e| format!("Failed to load config:\n{}", e))?;
+            print!("{}", config);
+        }
+        ["toggle", key] => {
+            let enabled =
+                settings::toggle(key).map_err(|e| format!("Failed to toggle {}:\n{}", key, e))?;
+            println!("Set {} = {}", key, enabled);
+        }
+        _ => return Err(format!("Unknown config command: {}", args.join(" "))),
+    }
+
+    Ok(())
+}
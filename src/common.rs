@@ -1,8 +1,8 @@
 use anyhow::Result;
 use csscolorparser::Color;
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Error, Formatter};
 use std::mem;
-use std::process;
 use std::{convert::TryFrom, ffi::c_void};
 use windows::{
     core::{PCWSTR, PWSTR},
@@ -13,8 +13,13 @@ use windows::{
                 DwmGetWindowAttribute, DWMWA_CLOAKED, DWM_CLOAKED_APP, DWM_CLOAKED_INHERITED,
                 DWM_CLOAKED_SHELL,
             },
-            Gdi::{GetMonitorInfoW, MonitorFromPoint, MONITORINFOEXW, MONITOR_DEFAULTTONEAREST},
+            Gdi::{
+                DeleteObject, EnumDisplayDevicesW, EnumDisplayMonitors, GetMonitorInfoW,
+                MonitorFromPoint, MonitorFromRect, MonitorFromWindow, DISPLAY_DEVICEW, HBRUSH, HDC,
+                HGDIOBJ, HMONITOR, MONITORINFOEXW, MONITOR_DEFAULTTONEAREST,
+            },
         },
+        System::Console::{AttachConsole, ATTACH_PARENT_PROCESS},
         System::Threading::{
             OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_INFORMATION,
         },
@@ -23,18 +28,21 @@ use windows::{
             WindowsAndMessaging::{
                 EnumWindows, GetCursorPos, GetForegroundWindow, GetWindowLongW, GetWindowRect,
                 GetWindowTextW, GetWindowThreadProcessId, IsIconic, IsWindow, IsWindowVisible,
-                MessageBoxW, SetForegroundWindow, SetWindowPos, GWL_EXSTYLE, HWND_TOP, MB_OK,
-                SWP_NOMOVE, SWP_NOSIZE, SWP_SHOWWINDOW, WINDOW_EX_STYLE, WS_EX_TOOLWINDOW,
+                MessageBoxW, SetClassLongPtrW, SetForegroundWindow, SetWindowPos,
+                EDD_GET_DEVICE_INTERFACE_NAME, GCLP_HBRBACKGROUND, GWL_EXSTYLE, HWND_TOP, IDYES,
+                MB_ICONWARNING, MB_OK, MB_YESNO, SWP_NOMOVE, SWP_NOSIZE, SWP_SHOWWINDOW,
+                WINDOW_EX_STYLE, WS_EX_TOOLWINDOW,
             },
         },
     },
 };
 
+use crate::logging;
 use crate::str_to_wide;
 use crate::window::Window;
 
 /// x & y coordinates are relative to top left of screen
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Rect {
     pub x: i32,
     pub y: i32,
@@ -142,9 +150,142 @@ pub unsafe fn get_active_monitor_name() -> String {
     String::from_utf16_lossy(&info.szDevice)
 }
 
-pub fn report_and_exit(error_msg: &str) -> ! {
-    show_msg_box(error_msg);
-    process::exit(1)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Monitor {
+    /// GDI device name, such as `\\.\DISPLAY1`. Can change between sessions.
+    pub name: String,
+    /// Stable identifier, see [`monitor_id`]
+    pub id: String,
+    pub rect: Rect,
+}
+
+/// The monitor under the cursor
+pub unsafe fn get_active_monitor() -> Monitor {
+    let active_monitor = {
+        let mut cursor_pos: POINT = mem::zeroed();
+        let _ = GetCursorPos(&mut cursor_pos);
+
+        MonitorFromPoint(cursor_pos, MONITOR_DEFAULTTONEAREST)
+    };
+
+    monitor_from_handle(active_monitor).unwrap_or_else(|| Monitor {
+        name: String::new(),
+        id: String::new(),
+        rect: Rect::zero(),
+    })
+}
+
+/// The monitor `hwnd` is mostly on
+pub unsafe fn monitor_of_window(hwnd: HWND) -> Option<Monitor> {
+    monitor_from_handle(MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST))
+}
+
+/// Work area of `monitor`, which excludes the taskbar and docked toolbars
+pub unsafe fn monitor_work_area(monitor: &Monitor) -> Rect {
+    let hmonitor = MonitorFromRect(&monitor.rect.into(), MONITOR_DEFAULTTONEAREST);
+
+    let mut info: MONITORINFOEXW = Default::default();
+    info.monitorInfo.cbSize = u32::try_from(std::mem::size_of::<MONITORINFOEXW>())
+        .expect("failed size_fo MONITORINFOEXW");
+
+    if !GetMonitorInfoW(hmonitor, &mut info as *mut MONITORINFOEXW as *mut _).as_bool() {
+        return monitor.rect;
+    }
+
+    info.monitorInfo.rcWork.into()
+}
+
+/// All connected monitors, in enumeration order
+pub fn enumerate_monitors() -> Vec<Monitor> {
+    let mut monitors: Vec<Monitor> = Vec::new();
+    unsafe {
+        let _ = EnumDisplayMonitors(
+            HDC::default(),
+            None,
+            Some(enum_monitors_callback),
+            LPARAM(&mut monitors as *mut _ as isize),
+        );
+    }
+    monitors
+}
+
+unsafe extern "system" fn enum_monitors_callback(
+    hmonitor: HMONITOR,
+    _hdc: HDC,
+    _rect: *mut RECT,
+    lparam: LPARAM,
+) -> BOOL {
+    let monitors = unsafe { &mut *(lparam.0 as *mut Vec<Monitor>) };
+
+    if let Some(monitor) = monitor_from_handle(hmonitor) {
+        monitors.push(monitor);
+    }
+
+    true.into()
+}
+
+unsafe fn monitor_from_handle(hmonitor: HMONITOR) -> Option<Monitor> {
+    let mut info: MONITORINFOEXW = Default::default();
+    info.monitorInfo.cbSize = u32::try_from(std::mem::size_of::<MONITORINFOEXW>())
+        .expect("failed size_fo MONITORINFOEXW");
+
+    if !GetMonitorInfoW(hmonitor, &mut info as *mut MONITORINFOEXW as *mut _).as_bool() {
+        return None;
+    }
+
+    let name = wide_to_string(&info.szDevice);
+    let rect: Rect = info.monitorInfo.rcMonitor.into();
+    let id = monitor_id(&name, rect);
+
+    Some(Monitor { name, id, rect })
+}
+
+/// Device interface path of the display attached to `device_name`, derived from
+/// its EDID, such as `\\?\DISPLAY#DEL4105#...`. Falls back to a resolution and
+/// position fingerprint when the path isn't available.
+unsafe fn monitor_id(device_name: &str, rect: Rect) -> String {
+    let device_name = str_to_wide!(device_name);
+
+    let mut device: DISPLAY_DEVICEW = mem::zeroed();
+    device.cb = mem::size_of::<DISPLAY_DEVICEW>() as u32;
+
+    if EnumDisplayDevicesW(
+        PCWSTR(device_name.as_ptr()),
+        0,
+        &mut device,
+        EDD_GET_DEVICE_INTERFACE_NAME,
+    )
+    .as_bool()
+    {
+        let device_id = wide_to_string(&device.DeviceID);
+        if !device_id.is_empty() {
+            return device_id;
+        }
+    }
+
+    format!("{}x{}@{},{}", rect.width, rect.height, rect.x, rect.y)
+}
+
+/// Converts a null terminated wide string buffer
+fn wide_to_string(wide: &[u16]) -> String {
+    let len = wide.iter().position(|c| *c == 0).unwrap_or(wide.len());
+    String::from_utf16_lossy(&wide[..len])
+}
+
+/// Asks a yes / no question, returning true if yes was chosen
+pub fn ask_yes_no(message: &str) -> bool {
+    let mut message = str_to_wide!(message);
+    let message_pwstr = PWSTR(message.as_mut_ptr());
+    let hwnd: HWND = Default::default();
+
+    unsafe {
+        MessageBoxW(
+            hwnd,
+            message_pwstr,
+            PCWSTR::null(),
+            MB_YESNO | MB_ICONWARNING,
+        ) == IDYES
+    }
 }
 
 pub fn show_msg_box(message: &str) {
@@ -157,6 +298,14 @@ pub fn show_msg_box(message: &str) {
     }
 }
 
+/// Attaches to the console of the launching process, so command line output is
+/// visible even though release builds use the windows subsystem
+pub fn attach_console() {
+    unsafe {
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
 pub fn LOWORD(l: usize) -> u16 {
     (l & 0xffff) as u16
 }
@@ -170,14 +319,23 @@ pub fn color_to_colorref(color: &Color) -> COLORREF {
     COLORREF(r as u32 | ((g as u32) << 8) | ((b as u32) << 16))
 }
 
+/// Makes `brush` the background of `hwnd`'s window class, deleting the brush it
+/// replaces so reopening a window doesn't leak one each time
+pub unsafe fn set_class_brush(hwnd: HWND, brush: HBRUSH) {
+    let previous = SetClassLongPtrW(hwnd, GCLP_HBRBACKGROUND, brush.0) as isize;
+
+    if previous != 0 && previous != brush.0 {
+        let _ = DeleteObject(HGDIOBJ(previous));
+    }
+}
+
 unsafe fn window_process_and_thread_id(hwnd: HWND) -> (u32, u32) {
     let mut process_id: u32 = 0;
     let thread_id = GetWindowThreadProcessId(hwnd, Some(&mut process_id));
     (process_id, thread_id)
 }
 
-#[allow(dead_code)]
-unsafe fn window_exe(hwnd: HWND) -> String {
+pub unsafe fn window_exe(hwnd: HWND) -> String {
     let mut len = 260_u32;
     let mut path: Vec<u16> = vec![0; len as usize];
     let path_pwstr = PWSTR(path.as_mut_ptr());
@@ -324,6 +482,16 @@ pub fn previous_window(windows: &[HWND]) -> Option<&HWND> {
 unsafe extern "system" fn enum_windows_callback(hwnd: HWND, lparam: LPARAM) -> BOOL {
     let window_list = unsafe { &mut *(lparam.0 as *mut Vec<HWND>) };
 
+    if is_navigable_window(hwnd) {
+        window_list.push(hwnd);
+    }
+
+    true.into()
+}
+
+/// Whether `hwnd` is a window the user works with, one that's visible, not
+/// minimized and not a tool window
+pub unsafe fn is_navigable_window(hwnd: HWND) -> bool {
     let is_visible: bool = IsWindowVisible(hwnd).into();
     let is_iconic: bool = IsIconic(hwnd).into();
     let ex_style = WINDOW_EX_STYLE(GetWindowLongW(hwnd, GWL_EXSTYLE) as u32);
@@ -331,11 +499,7 @@ unsafe extern "system" fn enum_windows_callback(hwnd: HWND, lparam: LPARAM) -> B
     let is_window: bool = IsWindow(hwnd).into();
     let is_cloaked = window_is_cloaked(hwnd);
 
-    if is_window && is_visible && !is_tool_window && !is_cloaked && !is_iconic {
-        window_list.push(hwnd);
-    }
-
-    true.into()
+    is_window && is_visible && !is_tool_window && !is_cloaked && !is_iconic
 }
 
 pub fn focus_window(hwnd: HWND) -> bool {
@@ -345,8 +509,12 @@ pub fn focus_window(hwnd: HWND) -> bool {
     }];
 
     unsafe {
-        SendInput(&event, std::mem::size_of::<INPUT>() as i32);
-        let _ = SetWindowPos(
+        // A synthetic input event lets this process take the foreground
+        if SendInput(&event, std::mem::size_of::<INPUT>() as i32) == 0 {
+            logging::log_win32_error("SendInput", hwnd, &windows::core::Error::from_win32());
+        }
+
+        if let Err(e) = SetWindowPos(
             hwnd,
             HWND_TOP,
             0,
@@ -354,7 +522,15 @@ pub fn focus_window(hwnd: HWND) -> bool {
             0,
             0,
             SWP_NOMOVE | SWP_NOSIZE | SWP_SHOWWINDOW,
-        );
-        SetForegroundWindow(hwnd).into()
+        ) {
+            logging::log_win32_error("SetWindowPos", hwnd, &e);
+        }
+
+        let focused = SetForegroundWindow(hwnd).as_bool();
+        if !focused {
+            log::debug!("SetForegroundWindow didn't focus window {:#x}", hwnd.0);
+        }
+
+        focused
     }
 }
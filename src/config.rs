@@ -1,48 +1,140 @@
-use std::fs::{create_dir_all, write, File};
-use std::io::Read;
+use std::env;
+use std::fs::{create_dir_all, read_to_string, write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
 
 use anyhow::format_err;
 use csscolorparser::Color;
+use log::LevelFilter;
 use serde::{Deserialize, Serialize};
-use toml_edit::{value, DocumentMut};
+use toml_edit::{ImDocument, Item};
 
+use crate::action::{Action, Direction};
+use crate::common::Monitor;
+use crate::migrate::{migrate_config_file, migrated_config, CONFIG_VERSION};
+use crate::validate::{validate_config, ConfigIssue};
 use crate::Result;
 
 static EXAMPLE_CONFIG: &str = r#"
 # Example config file for Grout
 
+# Version of the config format. Older configs are updated automatically when loaded
+version = 1
+
+# Other config files to merge in first, relative to this one. Keys set in this
+# file override them. config.<computer name>.toml next to this file is merged
+# last, if it exists, for machine specific settings
+#include = ["shared.toml"]
+
 # Margin between windows, in pixels
 margins = 10
 
 # Padding between edge of monitor and windows, in pixels
 window_padding = 10
 
-# Hotkey to activate grid. Valid modifiers are CTRL, ALT, SHIFT, WIN
+# Hotkey to activate grid. Valid modifiers are CTRL, ALT, SHIFT, WIN and their
+# left / right variants (LCTRL, RALT, ...). Keys can be letters, digits, F1 - F24,
+# named keys such as LEFT, PAGEUP, NUMPAD5, VOLUMEUP, SEMICOLON, or any character
+# on the current keyboard layout. PLUS is the = / + key on US layouts, NUMPADADD
+# is + on the number pad.
 hotkey = "CTRL+ALT+S"
 
 # Automatically launch program on startup
 auto_start = false
 
-# Optional hotkeys
-#[optional_hotkeys]
-# Hotkey to activate grid for a quick resize. Grid will automatically close after resize operation.
-#quick_resize = "CTRL+ALT+Q"
+# Detail written to grout.log in the logs directory next to this file: off,
+# error, warn, info, debug or trace. The log can be opened from the tray menu.
+#log_level = "info"
+
+# Additional key bindings. Each binding maps a key combination to an action,
+# with the action's arguments alongside it. Available actions:
+#   open-grid       Open the grid, optionally in `profile`
+#   quick-resize    Open the grid and close it after one resize, optionally in `profile`
+#   maximize        Maximize / restore the active window
+#   focus           Focus the nearest window in `direction` (left, right, up, down)
+#   move-to-zone    Move the active window to `row`, `column` of the current grid,
+#                   counted from 0, spanning `row_span` / `column_span` zones, on
+#                   `monitor` (counted from 0) or the one under the cursor
+#   switch-profile  Switch to `profile`
+#   save-snapshot   Remember where every open window is as snapshot `name`
+#   restore-snapshot  Move windows back to where they were in snapshot `name`
+#   script          Call `function` from the scripts in the scripts directory
+#   exit            Exit the program
+# `hotkey` above is shorthand for an open-grid binding. The older
+# [optional_hotkeys] table is still read and translated to bindings.
+#[[bindings]]
+#key = "CTRL+ALT+Q"
+#action = "quick-resize"
+
+#[[bindings]]
+#key = "CTRL+ALT+X"
+#action = "maximize"
+
+#[[bindings]]
+#key = "ALT+H"
+#action = "focus"
+#direction = "left"
+
+#[[bindings]]
+#key = "CTRL+ALT+LEFT"
+#action = "move-to-zone"
+#row = 0
+#column = 0
+#row_span = 2
 
-# Hotkey to maximize / restore the active window
-#maximize_toggle = "CTRL+ALT+X"
+#[[bindings]]
+#key = "CTRL+ALT+C"
+#action = "open-grid"
+#profile = "Coding"
 
-# Navigate foreground window with hotkeys
-#[optional_hotkeys.navigate]
-#left = "ALT+H"
-#down = "ALT+J"
-#up = "ALT+K"
-#right = "ALT+L"
+# Leader key. Press it, then type one of the sequences to run its action. A
+# hint of the available sequences is shown until one is typed, Escape is
+# pressed, or timeout_ms passes without a key press. Sequence keys are
+# separated by spaces and take the same actions as [[bindings]].
+#[leader]
+#key = "WIN+G"
+#timeout_ms = 1500
+
+#[[leader.sequences]]
+#key = "h"
+#action = "focus"
+#direction = "left"
+
+#[[leader.sequences]]
+#key = "l"
+#action = "focus"
+#direction = "right"
+
+#[[leader.sequences]]
+#key = "m"
+#action = "maximize"
+
+#[[leader.sequences]]
+#key = "p 1"
+#action = "switch-profile"
+#profile = "Default"
+
+# Keys used while the grid is open. Keys can have modifiers, except
+# span_modifier which is the modifier held to span a selection across tiles
+# (SHIFT, CTRL, ALT, WIN or a left / right variant such as RCTRL). Profile keys
+# are set with `key` on each profile, e.g. key = "1" or key = "CTRL+1".
+#[grid_keys]
+#close = "ESCAPE"
+#add_column = "CTRL+RIGHT"
+#remove_column = "CTRL+LEFT"
+#add_row = "CTRL+UP"
+#remove_row = "CTRL+DOWN"
+#span_modifier = "SHIFT"
 
 # Optional customization of grid dimensions
 #[grid]
 #tile_width = 48
 #tile_height = 48
 #margins = 3
+# Rows / columns used the first time a grid is opened on a monitor
+#rows = 2
+#columns = 2
 
 # Optional customization of colors
 #[colors]
@@ -52,63 +144,388 @@ auto_start = false
 #tile_frame = "rgb(0, 0, 0)"
 #grid_background = "rgba(44, 44, 44, 1.0)"
 #preview = "rgba(0, 77, 128, 0.42)"
+
+# Optional named profiles. Switch between them from the grid window with
+# their key, or from the system tray. Each profile can override margins,
+# window_padding, [grid] and [colors]. Defaults to "Default" and
+# "Profile2" - "Profile6" bound to F1 - F6 when no profiles are listed.
+#[[profiles]]
+#name = "Default"
+#key = "F1"
+
+#[[profiles]]
+#name = "Coding"
+#display_name = "Coding (3 columns)"
+#key = "F2"
+#margins = 0
+#grid = { rows = 1, columns = 3 }
+#colors = { preview = "rgba(0, 128, 77, 0.42)" }
+
+# The last active profile is restored on startup. Set this to always start
+# on a fixed profile instead.
+#startup_profile = "Default"
+
+# Remember the last active profile separately for each monitor
+#profile_per_monitor = false
+
+# Profiles can switch automatically when the connected monitors match. Each
+# entry must match one monitor, unset fields match anything. device can be a
+# device name, a monitor id or a monitor alias. Set
+# restore_arrangement to move windows back to where they were last placed
+# with this profile.
+#[[profiles]]
+#name = "Docked"
+#restore_arrangement = true
+#monitors = [
+#    { device = "\\\\.\\DISPLAY1", width = 2560, height = 1440, x = 0, y = 0 },
+#    { width = 2560, height = 1440, x = 2560, y = 0 },
+#]
+
+# Profiles can be bound to applications by exe name or full path. The grid
+# opens in this profile while one of them is the foreground window.
+#[[profiles]]
+#name = "Photoshop"
+#applications = ["Photoshop.exe"]
+#grid = { rows = 1, columns = 3 }
+
+# Grid sizes are remembered per monitor using an id derived from the
+# monitor's EDID. Aliases give monitors a readable name that is used instead.
+# id can be the full id, part of it, or a device name.
+#[[monitor_aliases]]
+#alias = "Left"
+#id = "DEL4105"
+
+# Commands run when something happens: profile-changed, grid-opened,
+# grid-closed, window-placed, focus-changed, window-created, monitor-changed or
+# display-changed. Details are passed in environment variables such as
+# GROUT_HOOK_EVENT, GROUT_HOOK_PROFILE and GROUT_HOOK_WINDOW_EXE, and as JSON
+# in GROUT_HOOK_EVENT_JSON, or in the last argument with json_arg = true.
+# Commands run in the background and are stopped after timeout_ms (10000 by
+# default).
+#[[hooks]]
+#event = "profile-changed"
+#command = "powershell"
+#args = ["-File", "C:\\scripts\\status-bar.ps1"]
+#json_arg = true
+#timeout_ms = 5000
 "#;
 
-pub fn load_config() -> Result<Config> {
-    let mut config_path =
+/// Config file given with `--config`, overriding the usual location
+static CONFIG_PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Directory next to the executable that switches to portable mode when it exists
+const PORTABLE_DIR: &str = "grout";
+
+/// Uses `path` as the config file instead of looking it up. Only the first call
+/// has an effect, so it should happen before anything loads the config.
+pub fn set_config_path(path: PathBuf) {
+    let path = match env::current_dir() {
+        Ok(current_dir) => current_dir.join(path),
+        Err(_) => path,
+    };
+
+    let _ = CONFIG_PATH_OVERRIDE.set(path);
+}
+
+/// Path of `config.toml`, whether or not it exists yet
+pub fn config_path() -> Result<PathBuf> {
+    if let Some(path) = CONFIG_PATH_OVERRIDE.get() {
+        return Ok(path.clone());
+    }
+
+    Ok(config_dir()?.join("config.toml"))
+}
+
+/// Directory holding the config, cache and autostart copy of the executable. In
+/// order of precedence this is the directory of the `--config` file,
+/// `GROUT_CONFIG_DIR`, a `grout` directory next to the executable (portable mode),
+/// or `grout` in the user's config directory.
+pub fn config_dir() -> Result<PathBuf> {
+    if let Some(dir) = CONFIG_PATH_OVERRIDE.get().and_then(|path| path.parent()) {
+        return Ok(dir.to_path_buf());
+    }
+
+    if let Some(dir) = env::var_os(CONFIG_DIR_VAR).filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+
+    if let Some(dir) = portable_dir() {
+        return Ok(dir);
+    }
+
+    default_config_dir()
+}
+
+/// Whether the config lives somewhere other than the user's config directory, so
+/// programs started for the user need to be told where it is
+pub fn is_custom_location() -> bool {
+    match (config_dir(), default_config_dir()) {
+        (Ok(dir), Ok(default_dir)) => dir != default_dir || CONFIG_PATH_OVERRIDE.get().is_some(),
+        _ => false,
+    }
+}
+
+fn default_config_dir() -> Result<PathBuf> {
+    let mut config_dir =
         dirs::config_dir().ok_or_else(|| format_err!("Failed to get config directory"))?;
-    config_path.push("grout");
+    config_dir.push("grout");
+    Ok(config_dir)
+}
 
-    if !config_path.exists() {
-        create_dir_all(&config_path)?;
+fn portable_dir() -> Option<PathBuf> {
+    let exe_path = env::current_exe().ok()?;
+    let dir = exe_path.parent()?.join(PORTABLE_DIR);
+
+    if dir.is_dir() {
+        Some(dir)
+    } else {
+        None
+    }
+}
+
+pub fn load_config() -> Result<Config> {
+    let config_path = config_path()?;
+
+    if let Some(config_dir) = config_path.parent() {
+        if !config_dir.exists() {
+            create_dir_all(config_dir)?;
+        }
     }
 
-    config_path.push("config.toml");
     if !config_path.exists() {
         write(&config_path, EXAMPLE_CONFIG)?;
     }
 
-    config::Config::builder()
-        .add_source(config::File::new(
-            config_path.to_str().expect("invalid config path"),
+    let layers = read_layers(&config_path)?
+        .into_iter()
+        .map(|layer| ConfigLayer {
+            contents: migrate_config_file(&layer.path, layer.contents),
+            path: layer.path,
+        })
+        .collect();
+
+    merge_layers(&config_path, layers)
+}
+
+/// Loads the config as `load_config` does, without creating, migrating or
+/// rewriting any file, for commands that only inspect it
+pub fn read_config() -> Result<Config> {
+    let config_path = config_path()?;
+
+    let layers = read_layers(&config_path)?
+        .into_iter()
+        .map(|layer| ConfigLayer {
+            contents: migrated_config(layer.contents),
+            path: layer.path,
+        })
+        .collect();
+
+    merge_layers(&config_path, layers)
+}
+
+/// Checks the config as it would load with `config.toml` replaced by `contents`
+pub fn check_config_edit(contents: &str) -> Result<Config> {
+    let config_path = config_path()?;
+
+    let mut layers = read_layers(&config_path)?;
+    for layer in layers.iter_mut().filter(|layer| layer.path == config_path) {
+        layer.contents = contents.to_owned();
+    }
+
+    merge_layers(&config_path, layers)
+}
+
+/// Validates the layers, then merges them with the environment variable overrides.
+/// Problems with required keys are reported against `config_path`.
+fn merge_layers(config_path: &Path, layers: Vec<ConfigLayer>) -> Result<Config> {
+    let issues = validate_config(
+        config_path,
+        layers
+            .iter()
+            .map(|layer| (layer.path.as_path(), layer.contents.as_str())),
+    );
+
+    let mut builder = config::Config::builder();
+    for layer in &layers {
+        builder = builder.add_source(config::File::from_str(
+            &layer.contents,
             config::FileFormat::Toml,
-        ))
+        ));
+    }
+
+    if !issues.is_empty() {
+        let issues = issues
+            .iter()
+            .map(ConfigIssue::to_string)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        return Err(format_err!("{}", issues));
+    }
+
+    builder
+        .add_source(
+            config::Environment::with_prefix(ENV_PREFIX)
+                .prefix_separator("_")
+                .separator("__")
+                .try_parsing(true),
+        )
         .build()?
         .try_deserialize::<Config>()
         .map_err(|e| e.into())
 }
 
-pub fn toggle_autostart() -> Result<()> {
-    let mut config_path =
-        dirs::config_dir().ok_or_else(|| format_err!("Failed to get config directory"))?;
-    config_path.push("grout");
-    config_path.push("config.toml");
+/// Prefix of environment variables overriding config keys, e.g. `GROUT_MARGINS=5`
+/// or `GROUT_GRID__ROWS=3` for `grid.rows`
+const ENV_PREFIX: &str = "GROUT";
 
-    let mut config = File::open(&config_path)?;
-    let mut config_str = String::new();
+/// Environment variable moving the config directory
+const CONFIG_DIR_VAR: &str = "GROUT_CONFIG_DIR";
 
-    config.read_to_string(&mut config_str)?;
+/// A config file and its contents
+struct ConfigLayer {
+    path: PathBuf,
+    contents: String,
+}
 
-    let mut config_doc = config_str
-        .parse::<DocumentMut>()
-        .expect("invalid config.toml");
-    let enabled = if let Some(auto_start) = config_doc["auto_start"].as_bool() {
-        !auto_start
-    } else {
-        false
-    };
-    config_doc["auto_start"] = value(enabled);
+/// Reads `config.toml` and the per-machine overlay, each preceded by the files it
+/// includes, in the order they're merged
+fn read_layers(config_path: &Path) -> Result<Vec<ConfigLayer>> {
+    let mut layers = vec![];
+
+    read_with_includes(config_path, &mut layers, &mut vec![])?;
+
+    if let Some(host_config_path) = host_config_path(config_path) {
+        if host_config_path.exists() {
+            read_with_includes(&host_config_path, &mut layers, &mut vec![])?;
+        }
+    }
 
-    write(&config_path, config_doc.to_string())?;
+    Ok(layers)
+}
+
+fn read_with_includes(
+    path: &Path,
+    layers: &mut Vec<ConfigLayer>,
+    including: &mut Vec<PathBuf>,
+) -> Result<()> {
+    if including.iter().any(|including| including == path) {
+        return Err(format_err!("{} includes itself", path.display()));
+    }
+
+    let contents = read_to_string(path)
+        .map_err(|e| format_err!("Failed to read {}: {}", path.display(), e))?;
+
+    including.push(path.to_path_buf());
+
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    for include in includes(&contents) {
+        read_with_includes(&dir.join(include), layers, including)?;
+    }
+
+    including.pop();
+
+    layers.push(ConfigLayer {
+        path: path.to_path_buf(),
+        contents,
+    });
 
     Ok(())
 }
 
+/// Paths listed under `include`. Syntax errors are left for the validator.
+fn includes(contents: &str) -> Vec<String> {
+    contents
+        .parse::<ImDocument<_>>()
+        .ok()
+        .and_then(|document| {
+            document
+                .get("include")
+                .and_then(Item::as_array)
+                .map(|array| {
+                    array
+                        .iter()
+                        .filter_map(|include| include.as_str().map(str::to_owned))
+                        .collect()
+                })
+        })
+        .unwrap_or_default()
+}
+
+/// Machine specific overrides, `config.<hostname>.toml` next to `config.toml`
+fn host_config_path(config_path: &Path) -> Option<PathBuf> {
+    let host_name = env::var("COMPUTERNAME").ok()?;
+
+    Some(config_path.with_file_name(format!("config.{}.toml", host_name.to_lowercase())))
+}
+
+/// Every file the config is merged from, including a per-machine overlay that
+/// doesn't exist yet, so it can be watched for changes
+pub fn config_files() -> Vec<PathBuf> {
+    let config_path = match config_path() {
+        Ok(config_path) => config_path,
+        Err(_) => return vec![],
+    };
+
+    let mut files = match read_layers(&config_path) {
+        Ok(layers) => layers.into_iter().map(|layer| layer.path).collect(),
+        Err(_) => vec![config_path.clone()],
+    };
+
+    if let Some(host_config_path) = host_config_path(&config_path) {
+        if !files.contains(&host_config_path) {
+            files.push(host_config_path);
+        }
+    }
+
+    files
+}
+
+/// The merged config as TOML, preceded by the files and environment variables it
+/// was merged from
+pub fn effective_config() -> Result<String> {
+    let config = read_config()?;
+
+    let mut sources = config_files()
+        .into_iter()
+        .filter(|path| path.exists())
+        .map(|path| format!("# {}", path.display()))
+        .collect::<Vec<_>>();
+
+    let env_prefix = format!("{}_", ENV_PREFIX);
+    sources.extend(
+        env::vars()
+            .filter(|(name, _)| name.to_uppercase().starts_with(&env_prefix))
+            .map(|(name, value)| format!("# {}={}", name, value)),
+    );
+
+    Ok(format!(
+        "# Merged from:\n{}\n\n{}",
+        sources.join("\n"),
+        toml_edit::ser::to_string_pretty(&config)?
+    ))
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CustomGridConfig {
     pub tile_width: Option<u32>,
     pub tile_height: Option<u32>,
     pub margins: Option<u8>,
+    pub rows: Option<usize>,
+    pub columns: Option<usize>,
+}
+
+impl CustomGridConfig {
+    /// Fields set in `other` take precedence over fields set in `self`
+    fn merge(&self, other: &CustomGridConfig) -> CustomGridConfig {
+        CustomGridConfig {
+            tile_width: other.tile_width.or(self.tile_width),
+            tile_height: other.tile_height.or(self.tile_height),
+            margins: other.margins.or(self.margins),
+            rows: other.rows.or(self.rows),
+            columns: other.columns.or(self.columns),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -121,43 +538,377 @@ pub struct CustomColors {
     pub preview: Option<Color>,
 }
 
+impl CustomColors {
+    /// Fields set in `other` take precedence over fields set in `self`
+    fn merge(&self, other: &CustomColors) -> CustomColors {
+        CustomColors {
+            tile: other.tile.clone().or_else(|| self.tile.clone()),
+            tile_hovered: other
+                .tile_hovered
+                .clone()
+                .or_else(|| self.tile_hovered.clone()),
+            tile_selected: other
+                .tile_selected
+                .clone()
+                .or_else(|| self.tile_selected.clone()),
+            tile_frame: other.tile_frame.clone().or_else(|| self.tile_frame.clone()),
+            grid_background: other
+                .grid_background
+                .clone()
+                .or_else(|| self.grid_background.clone()),
+            preview: other.preview.clone().or_else(|| self.preview.clone()),
+        }
+    }
+}
+
+/// Describes one monitor of a setup. Unset fields match any monitor.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MonitorMatch {
+    pub device: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+}
+
+/// Command run when an event happens, see `hook.rs`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Hook {
+    /// Name of the event, such as `profile-changed`
+    pub event: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Passes the event as JSON in the last argument, after `args`
+    #[serde(default)]
+    pub json_arg: bool,
+    /// How long the command can run before it's killed
+    pub timeout_ms: Option<u64>,
+}
+
+/// Human readable name for a monitor, used in place of its id
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MonitorAlias {
+    pub alias: String,
+    /// Full monitor id, part of it such as `DEL4105`, or a device name such as `\\.\DISPLAY1`
+    pub id: String,
+}
+
+impl MonitorAlias {
+    fn matches(&self, monitor: &Monitor) -> bool {
+        let id = self.id.to_lowercase();
+
+        monitor.name.eq_ignore_ascii_case(&self.id) || monitor.id.to_lowercase().contains(&id)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub display_name: Option<String>,
+    pub key: Option<String>,
+    pub margins: Option<u8>,
+    pub window_padding: Option<u8>,
+    pub grid: Option<CustomGridConfig>,
+    pub colors: Option<CustomColors>,
+    pub monitors: Option<Vec<MonitorMatch>>,
+    #[serde(default)]
+    pub restore_arrangement: bool,
+    pub applications: Option<Vec<String>>,
+}
+
+impl Profile {
+    fn new(name: &str, key: &str) -> Self {
+        Profile {
+            name: name.to_owned(),
+            display_name: None,
+            key: Some(key.to_owned()),
+            margins: None,
+            window_padding: None,
+            grid: None,
+            colors: None,
+            monitors: None,
+            restore_arrangement: false,
+            applications: None,
+        }
+    }
+
+    pub fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Keys used while the grid window is open. Each key can have modifiers, e.g.
+/// `CTRL+RIGHT`, except `span_modifier` which is a single modifier.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GridKeys {
+    pub close: Option<String>,
+    pub add_column: Option<String>,
+    pub remove_column: Option<String>,
+    pub add_row: Option<String>,
+    pub remove_row: Option<String>,
+    pub span_modifier: Option<String>,
+}
+
+/// A key combination bound to an action
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Binding {
+    pub key: String,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+/// A key that waits for one of `sequences` to be typed. Sequence keys are
+/// separated by spaces, e.g. `m 1`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Leader {
+    pub key: String,
+    /// Milliseconds to wait for the next key of a sequence
+    pub timeout_ms: Option<u64>,
+    pub sequences: Option<Vec<Binding>>,
+}
+
+/// Navigation hotkeys from before `[[bindings]]`, translated to focus bindings
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct NavigateHotkeys {
-    pub left: Option<String>,
-    pub down: Option<String>,
-    pub up: Option<String>,
-    pub right: Option<String>,
+pub struct LegacyNavigateHotkeys {
+    left: Option<String>,
+    down: Option<String>,
+    up: Option<String>,
+    right: Option<String>,
 }
 
+/// Hotkeys from before `[[bindings]]`, translated to bindings by `Config::bindings`
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct OptionalHotkeys {
-    pub quick_resize: Option<String>,
-    pub maximize_toggle: Option<String>,
-    pub navigate: Option<NavigateHotkeys>,
-    pub quick_exit: Option<String>,
+pub struct LegacyOptionalHotkeys {
+    quick_resize: Option<String>,
+    maximize_toggle: Option<String>,
+    navigate: Option<LegacyNavigateHotkeys>,
+    quick_exit: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
+    pub version: Option<u32>,
     pub margins: u8,
     pub window_padding: u8,
-    pub hotkey: String,
-    pub optional_hotkeys: Option<OptionalHotkeys>,
+    pub hotkey: Option<String>,
+    pub optional_hotkeys: Option<LegacyOptionalHotkeys>,
+    pub bindings: Option<Vec<Binding>>,
+    pub grid_keys: Option<GridKeys>,
+    pub leader: Option<Leader>,
     pub auto_start: bool,
     pub grid: Option<CustomGridConfig>,
     pub colors: Option<CustomColors>,
+    pub profiles: Option<Vec<Profile>>,
+    pub startup_profile: Option<String>,
+    #[serde(default)]
+    pub profile_per_monitor: bool,
+    pub monitor_aliases: Option<Vec<MonitorAlias>>,
+    pub hooks: Option<Vec<Hook>>,
+    pub log_level: Option<String>,
+}
+
+impl Config {
+    /// Configured profiles, or the legacy F1 - F6 profiles if none are configured
+    pub fn profiles(&self) -> Vec<Profile> {
+        match &self.profiles {
+            Some(profiles) if !profiles.is_empty() => profiles.clone(),
+            _ => vec![
+                Profile::new("Default", "F1"),
+                Profile::new("Profile2", "F2"),
+                Profile::new("Profile3", "F3"),
+                Profile::new("Profile4", "F4"),
+                Profile::new("Profile5", "F5"),
+                Profile::new("Profile6", "F6"),
+            ],
+        }
+    }
+
+    pub fn profile(&self, name: &str) -> Option<Profile> {
+        self.profiles()
+            .into_iter()
+            .find(|profile| profile.name == name)
+    }
+
+    pub fn default_profile(&self) -> String {
+        self.profiles()
+            .first()
+            .map(|profile| profile.name.clone())
+            .unwrap_or_else(|| "Default".to_owned())
+    }
+
+    /// Returns a copy of this config with the overrides of profile `name` applied
+    pub fn with_profile(&self, name: &str) -> Config {
+        let mut config = self.clone();
+
+        if let Some(profile) = self.profile(name) {
+            if let Some(margins) = profile.margins {
+                config.margins = margins;
+            }
+            if let Some(window_padding) = profile.window_padding {
+                config.window_padding = window_padding;
+            }
+            if let Some(grid) = &profile.grid {
+                config.grid = Some(match &self.grid {
+                    Some(base) => base.merge(grid),
+                    None => grid.clone(),
+                });
+            }
+            if let Some(colors) = &profile.colors {
+                config.colors = Some(match &self.colors {
+                    Some(base) => base.merge(colors),
+                    None => colors.clone(),
+                });
+            }
+        }
+
+        config
+    }
+
+    /// Every key binding, paired with the config key it was read from. The legacy
+    /// `hotkey` and `[optional_hotkeys]` keys are translated to their actions.
+    pub fn bindings(&self) -> Vec<(String, Binding)> {
+        let mut bindings = vec![];
+        let mut bind = |config_key: &str, key: &Option<String>, action: Action| {
+            if let Some(key) = key {
+                bindings.push((
+                    config_key.to_owned(),
+                    Binding {
+                        key: key.clone(),
+                        action,
+                    },
+                ));
+            }
+        };
+
+        bind("hotkey", &self.hotkey, Action::OpenGrid { profile: None });
+
+        if let Some(optional_hotkeys) = &self.optional_hotkeys {
+            bind(
+                "optional_hotkeys.quick_resize",
+                &optional_hotkeys.quick_resize,
+                Action::QuickResize { profile: None },
+            );
+            bind(
+                "optional_hotkeys.maximize_toggle",
+                &optional_hotkeys.maximize_toggle,
+                Action::Maximize,
+            );
+            bind(
+                "optional_hotkeys.quick_exit",
+                &optional_hotkeys.quick_exit,
+                Action::Exit,
+            );
+
+            if let Some(navigate) = &optional_hotkeys.navigate {
+                let directions = [
+                    ("left", &navigate.left, Direction::Left),
+                    ("down", &navigate.down, Direction::Down),
+                    ("up", &navigate.up, Direction::Up),
+                    ("right", &navigate.right, Direction::Right),
+                ];
+
+                for (name, key, direction) in directions {
+                    bind(
+                        &format!("optional_hotkeys.navigate.{}", name),
+                        key,
+                        Action::Focus { direction },
+                    );
+                }
+            }
+        }
+
+        if let Some(leader) = &self.leader {
+            bindings.push((
+                "leader.key".to_owned(),
+                Binding {
+                    key: leader.key.clone(),
+                    action: Action::Leader,
+                },
+            ));
+        }
+
+        for (idx, binding) in self.bindings.iter().flatten().enumerate() {
+            bindings.push((format!("bindings[{}]", idx), binding.clone()));
+        }
+
+        bindings
+    }
+
+    /// Name a monitor is saved under in the cache: its alias if it has one, else its id
+    pub fn monitor_key(&self, monitor: &Monitor) -> String {
+        self.monitor_alias(monitor)
+            .unwrap_or_else(|| monitor.id.clone())
+    }
+
+    pub fn monitor_alias(&self, monitor: &Monitor) -> Option<String> {
+        self.monitor_aliases
+            .as_ref()?
+            .iter()
+            .find(|alias| alias.matches(monitor))
+            .map(|alias| alias.alias.clone())
+    }
+
+    pub fn preview_color(&self) -> Color {
+        self.colors
+            .as_ref()
+            .and_then(|colors| colors.preview.clone())
+            .unwrap_or_else(|| [0, 77, 128, 107].into())
+    }
+
+    pub fn tile_color(&self) -> Color {
+        self.colors
+            .as_ref()
+            .and_then(|colors| colors.tile.clone())
+            .unwrap_or_else(|| [178, 178, 178, 255].into())
+    }
+
+    /// Most detailed level written to the log, `info` unless set
+    pub fn log_level(&self) -> LevelFilter {
+        self.log_level
+            .as_deref()
+            .and_then(|level| level.parse().ok())
+            .unwrap_or(LevelFilter::Info)
+    }
+
+    pub fn leader_timeout(&self) -> Duration {
+        let timeout_ms = self
+            .leader
+            .as_ref()
+            .and_then(|leader| leader.timeout_ms)
+            .unwrap_or(1500);
+
+        Duration::from_millis(timeout_ms)
+    }
+
+    pub fn grid_background(&self) -> Color {
+        self.colors
+            .as_ref()
+            .and_then(|colors| colors.grid_background.clone())
+            .unwrap_or_else(|| [44, 44, 44, 255].into())
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
+            version: Some(CONFIG_VERSION as u32),
             margins: 10,
             window_padding: 10,
-            hotkey: "CTRL+ALT+S".to_string(),
+            hotkey: Some("CTRL+ALT+S".to_string()),
             optional_hotkeys: None,
+            bindings: None,
+            grid_keys: None,
+            leader: None,
             auto_start: false,
             grid: None,
             colors: None,
+            profiles: None,
+            startup_profile: None,
+            profile_per_monitor: false,
+            monitor_aliases: None,
+            hooks: None,
+            log_level: None,
         }
     }
 }
@@ -1,23 +1,51 @@
 use crossbeam_channel::{select, Receiver};
+use std::fs;
 use std::mem;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use windows::Win32::{
     Foundation::{HMODULE, HWND},
     UI::{
         Accessibility::{SetWinEventHook, HWINEVENTHOOK},
         WindowsAndMessaging::{
-            DispatchMessageW, PeekMessageW, TranslateMessage, EVENT_SYSTEM_FOREGROUND,
+            DispatchMessageW, GetAncestor, GetMessageW, PeekMessageW, TranslateMessage,
+            CHILDID_SELF, EVENT_OBJECT_SHOW, EVENT_SYSTEM_FOREGROUND, GA_ROOT, OBJID_WINDOW,
             PEEK_MESSAGE_REMOVE_TYPE, WINEVENT_OUTOFCONTEXT,
         },
     },
 };
 
-use crate::common::get_active_monitor_name;
+use crate::bus::{self, Event, WindowInfo};
+use crate::common::{get_active_monitor_name, is_navigable_window};
+use crate::config;
 use crate::window::Window;
 use crate::Message;
 use crate::CHANNEL;
 
+/// Publishes an event whenever the foreground window changes or a window is
+/// shown, for as long as the program runs
+pub fn spawn_window_hook() {
+    thread::spawn(|| unsafe {
+        for event in [EVENT_SYSTEM_FOREGROUND, EVENT_OBJECT_SHOW].iter() {
+            SetWinEventHook(
+                *event,
+                *event,
+                HMODULE::default(),
+                Some(window_callback),
+                0,
+                0,
+                WINEVENT_OUTOFCONTEXT,
+            );
+        }
+
+        let mut msg = mem::zeroed();
+        while GetMessageW(&mut msg, HWND::default(), 0, 0).into() {
+            let _ = TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    });
+}
+
 pub fn spawn_foreground_hook(close_msg: Receiver<()>) {
     thread::spawn(move || unsafe {
         SetWinEventHook(
@@ -71,6 +99,36 @@ pub fn spawn_track_monitor_thread(close_msg: Receiver<()>) {
     });
 }
 
+/// Polls the config file, sending `ConfigChange` after it's modified
+pub fn spawn_config_watcher() {
+    thread::spawn(|| {
+        let sender = &CHANNEL.0.clone();
+
+        let mut previous_modified = config_modified();
+
+        loop {
+            thread::sleep(Duration::from_secs(1));
+
+            let modified = config_modified();
+            if modified != previous_modified {
+                // Give editors that save in several steps time to finish writing
+                thread::sleep(Duration::from_millis(250));
+                previous_modified = config_modified();
+
+                let _ = sender.send(Message::ConfigChange);
+            }
+        }
+    });
+}
+
+/// Modification times of every file the config is merged from
+fn config_modified() -> Vec<Option<SystemTime>> {
+    config::config_files()
+        .into_iter()
+        .map(|path| fs::metadata(path).ok()?.modified().ok())
+        .collect()
+}
+
 unsafe extern "system" fn callback(
     _hWinEventHook: HWINEVENTHOOK,
     _event: u32,
@@ -83,3 +141,31 @@ unsafe extern "system" fn callback(
     let sender = &CHANNEL.0.clone();
     let _ = sender.send(Message::ActiveWindowChange(Window(hwnd)));
 }
+
+unsafe extern "system" fn window_callback(
+    _hWinEventHook: HWINEVENTHOOK,
+    event: u32,
+    hwnd: HWND,
+    idObject: i32,
+    idChild: i32,
+    _idEventThread: u32,
+    _dwmsEventTime: u32,
+) {
+    if event == EVENT_SYSTEM_FOREGROUND {
+        bus::publish(Event::FocusChanged {
+            window: WindowInfo::from(Window(hwnd)),
+        });
+        return;
+    }
+
+    // Show events are also sent for child windows, carets and cursors
+    let is_top_level = idObject == OBJID_WINDOW.0
+        && idChild == CHILDID_SELF as i32
+        && GetAncestor(hwnd, GA_ROOT) == hwnd;
+
+    if is_top_level && is_navigable_window(hwnd) {
+        bus::publish(Event::WindowCreated {
+            window: WindowInfo::from(Window(hwnd)),
+        });
+    }
+}
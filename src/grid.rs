@@ -2,22 +2,35 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::mem;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 use windows::Win32::{
     Foundation::COLORREF,
     Graphics::Gdi::{
-        BeginPaint, CreateSolidBrush, DeleteObject, EndPaint, FillRect, FrameRect, HBRUSH, HDC,
-        PAINTSTRUCT,
+        BeginPaint, CreateSolidBrush, DeleteObject, DrawTextW, EndPaint, FillRect, FrameRect,
+        InvalidateRect, SetBkMode, SetTextColor, DT_CENTER, DT_END_ELLIPSIS, DT_SINGLELINE,
+        DT_VCENTER, HBRUSH, HDC, PAINTSTRUCT, TRANSPARENT,
     },
 };
 
-use crate::common::{color_to_colorref, get_active_monitor_name, get_work_area, Rect};
+use crate::bus::ZoneSpan;
+use crate::cache::{backup_path, cache_file, write_atomic};
+use crate::common::{
+    color_to_colorref, enumerate_monitors, get_active_monitor, get_work_area, monitor_work_area,
+    Monitor, Rect,
+};
 use crate::config::Config;
+use crate::hotkey::GridKeyMap;
+use crate::profile::current_profile;
+use crate::str_to_wide;
 use crate::window::Window;
-use crate::ACTIVE_PROFILE;
+use crate::Result;
+
+/// Height of the strip above the tiles showing the active profile
+const HEADER_HEIGHT: u32 = 18;
 
 pub struct Grid {
-    pub shift_down: bool,
-    pub control_down: bool,
+    pub span_down: bool,
     pub cursor_down: bool,
     pub selected_tile: Option<(usize, usize)>,
     pub hovered_tile: Option<(usize, usize)>,
@@ -25,6 +38,10 @@ pub struct Grid {
     pub grid_window: Option<Window>,
     pub previous_resize: Option<(Window, Rect)>,
     pub quick_resize: bool,
+    pub keys: GridKeyMap,
+    /// Area the zones are laid out in, the work area of the monitor under the
+    /// cursor if None
+    work_area: Option<Rect>,
     grid_margins: u8,
     zone_margins: u8,
     border_margins: u8,
@@ -33,6 +50,8 @@ pub struct Grid {
     configs: GridConfigs,
     tile_width: u32,
     tile_height: u32,
+    profile_name: String,
+    background_color: COLORREF,
     tile_frame_color: COLORREF,
     tile_normal_color: COLORREF,
     tile_hovered_color: COLORREF,
@@ -43,6 +62,9 @@ pub struct Grid {
 pub struct GridConfig {
     rows: usize,
     columns: usize,
+    /// Seconds since the unix epoch when the grid was last opened or resized
+    #[serde(default)]
+    last_used: u64,
 }
 
 impl Default for GridConfig {
@@ -50,73 +72,278 @@ impl Default for GridConfig {
         GridConfig {
             rows: 2,
             columns: 2,
+            last_used: 0,
         }
     }
 }
 
+impl GridConfig {
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn last_used(&self) -> u64 {
+        self.last_used
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Debug)]
 pub struct GridConfigKey {
     monitor: String,
     profile: String,
 }
 
-impl Default for GridConfigKey {
-    fn default() -> Self {
-        let monitor = unsafe { get_active_monitor_name() };
-        let profile = ACTIVE_PROFILE.lock().unwrap().clone();
+impl GridConfigKey {
+    pub fn monitor(&self) -> &str {
+        &self.monitor
+    }
+
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
 
-        GridConfigKey { monitor, profile }
+    /// Key for the monitor under the cursor and the current profile
+    pub fn new(config: &Config) -> Self {
+        GridConfigKey::for_monitor(config, &unsafe { get_active_monitor() })
+    }
+
+    /// Key for `monitor` and the current profile
+    pub fn for_monitor(config: &Config, monitor: &Monitor) -> Self {
+        GridConfigKey {
+            monitor: config.monitor_key(monitor),
+            profile: current_profile(),
+        }
     }
 }
 
 pub type GridConfigs = HashMap<GridConfigKey, GridConfig>;
+
+/// Version of the grid cache format written by this build. Version 0 is the
+/// unversioned map written by earlier releases, version 1 lacks `last_used`.
+const GRID_CACHE_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct GridCacheFile {
+    version: u32,
+    configs: GridConfigs,
+}
+
 pub trait GridCache {
     fn load() -> GridConfigs;
     fn save(&self);
+    fn load_from(path: &Path) -> Result<(GridConfigs, u32)>;
+    fn save_to(&self, path: &Path) -> Result<()>;
+    fn migrate_monitor_keys(&mut self, config: &Config) -> bool;
 }
 
 impl GridCache for GridConfigs {
+    /// Loads the grid cache, upgrading entries saved by an older version in memory
+    /// only, so reading the cache never writes it. `upgrade_grid_cache` saves the
+    /// upgrade.
     fn load() -> GridConfigs {
-        if let Some(mut config_path) = dirs::config_dir() {
-            config_path.push("grout");
-            config_path.push("cache");
+        match cache_file("grid.ron") {
+            Ok(path) => load_cache(&path).0,
+            Err(e) => {
+                log::warn!("Failed to locate grid cache: {}", e);
+                HashMap::new()
+            }
+        }
+    }
+
+    fn save(&self) {
+        if let Err(e) = cache_file("grid.ron").and_then(|path| self.save_to(&path)) {
+            log::warn!("Failed to save grid cache: {}", e);
+        }
+    }
+
+    /// Loads a grid cache of any known version, returning the version it was saved with
+    fn load_from(path: &Path) -> Result<(GridConfigs, u32)> {
+        let contents = fs::read_to_string(path)?;
+
+        match ron::de::from_str::<GridCacheFile>(&contents) {
+            Ok(file) => {
+                if file.version > GRID_CACHE_VERSION {
+                    log::warn!(
+                        "Grid cache {} has newer version {}, some entries may be ignored",
+                        path.display(),
+                        file.version
+                    );
+                }
 
-            if !config_path.exists() {
-                let _ = fs::create_dir_all(&config_path);
+                Ok((file.configs, file.version))
             }
+            Err(e) => match ron::de::from_str::<GridConfigs>(&contents) {
+                Ok(configs) => Ok((configs, 0)),
+                Err(_) => Err(e.into()),
+            },
+        }
+    }
 
-            config_path.push("grid.ron");
+    fn save_to(&self, path: &Path) -> Result<()> {
+        let file = GridCacheFile {
+            version: GRID_CACHE_VERSION,
+            configs: self.clone(),
+        };
 
-            if let Ok(file) = fs::File::open(config_path) {
-                if let Ok(config) = ron::de::from_reader(file) {
-                    return config;
+        write_atomic(path, &ron::ser::to_string(&file)?)
+    }
+
+    /// Re-keys entries saved under a monitor's GDI device name, such as `\\.\DISPLAY1`,
+    /// or under a monitor id that has since been given an alias. Returns true if
+    /// any were.
+    fn migrate_monitor_keys(&mut self, config: &Config) -> bool {
+        let needs_migration = config.monitor_aliases.is_some()
+            || self.keys().any(|key| key.monitor.starts_with("\\\\.\\"));
+        if !needs_migration {
+            return false;
+        }
+
+        let monitors = enumerate_monitors();
+        let keys = self.keys().cloned().collect::<Vec<_>>();
+        let mut migrated = false;
+
+        for key in keys {
+            let device_name = key.monitor.trim_end_matches('\0');
+
+            let monitor = monitors
+                .iter()
+                .find(|monitor| monitor.name == device_name || monitor.id == key.monitor);
+
+            if let Some(monitor) = monitor {
+                let migrated_key = GridConfigKey {
+                    monitor: config.monitor_key(monitor),
+                    profile: key.profile.clone(),
+                };
+
+                if migrated_key != key && !self.contains_key(&migrated_key) {
+                    if let Some(grid_config) = self.remove(&key) {
+                        self.insert(migrated_key, grid_config);
+                        migrated = true;
+                    }
                 }
             }
         }
 
-        let mut config = HashMap::new();
-        config.insert(GridConfigKey::default(), GridConfig::default());
-        config
+        migrated
     }
+}
 
-    fn save(&self) {
-        if let Some(mut config_path) = dirs::config_dir() {
-            config_path.push("grout");
-            config_path.push("cache");
-            config_path.push("grid.ron");
+/// Upgrades the grid cache to the current version and re-keys its entries for the
+/// connected monitors, saving it if anything changed. Run by the running instance
+/// at startup and when the config is reloaded, rather than each time a grid is built.
+pub fn upgrade_grid_cache(config: &Config) {
+    let path = match cache_file("grid.ron") {
+        Ok(path) => path,
+        Err(e) => {
+            log::warn!("Failed to locate grid cache: {}", e);
+            return;
+        }
+    };
+
+    let (mut configs, upgraded) = load_cache(&path);
+    let migrated = configs.migrate_monitor_keys(config);
+
+    if upgraded || migrated {
+        if let Err(e) = save_upgraded(&configs, &path) {
+            log::warn!("Failed to save grid cache: {}", e);
+        }
+    }
+}
+
+/// Saves `configs` to `path`, first moving a corrupt cache there aside so saving
+/// doesn't replace the backup it was restored from
+fn save_upgraded(configs: &GridConfigs, path: &Path) -> Result<()> {
+    if path.exists() && GridConfigs::load_from(path).is_err() {
+        fs::rename(path, path.with_extension("ron.corrupt"))?;
+    }
+
+    configs.save_to(path)
+}
+
+/// Loads the grid cache at `path`, falling back to its backup if it can't be read,
+/// and upgrades entries saved by an older version in memory. Never writes the
+/// cache. Returns true if it was restored or upgraded, so it should be saved in
+/// the current version.
+fn load_cache(path: &Path) -> (GridConfigs, bool) {
+    let backup = backup_path(path);
+    if !path.exists() && !backup.exists() {
+        return (HashMap::new(), false);
+    }
 
-            if let Ok(serialized) = ron::ser::to_string(&self) {
-                let _ = fs::write(config_path, serialized);
+    let (mut configs, version) = match GridConfigs::load_from(path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            log::warn!(
+                "Failed to load grid cache {}, restoring from backup: {}",
+                path.display(),
+                e
+            );
+
+            match GridConfigs::load_from(&backup) {
+                Ok((configs, _)) => (configs, 0),
+                Err(e) => {
+                    log::warn!(
+                        "Failed to load grid cache backup {}, starting with an empty cache: {}",
+                        backup.display(),
+                        e
+                    );
+                    return (HashMap::new(), false);
+                }
             }
         }
+    };
+
+    if version >= GRID_CACHE_VERSION {
+        return (configs, false);
     }
+
+    log::info!(
+        "Migrating grid cache from version {} to {}",
+        version,
+        GRID_CACHE_VERSION
+    );
+
+    // Entries from before last use was tracked count as used now, so they aren't pruned
+    let now = unix_time();
+    configs
+        .values_mut()
+        .filter(|grid_config| grid_config.last_used == 0)
+        .for_each(|grid_config| grid_config.last_used = now);
+
+    (configs, true)
 }
 
 impl From<&Config> for Grid {
     fn from(config: &Config) -> Self {
+        Grid::new(config, None)
+    }
+}
+
+impl Grid {
+    /// Grid laid out on `monitor` with the dimensions last used there, rather than
+    /// on the monitor under the cursor
+    pub fn for_monitor(config: &Config, monitor: &Monitor) -> Self {
+        Grid::new(config, Some(monitor))
+    }
+
+    fn new(config: &Config, monitor: Option<&Monitor>) -> Self {
+        let profile = current_profile();
+        let profile_name = config
+            .profile(&profile)
+            .map(|profile| profile.display_name().to_owned())
+            .unwrap_or_else(|| profile.clone());
+
+        let config = &config.with_profile(&profile);
+
         let mut tile_width = 48;
         let mut tile_height = 48;
         let mut grid_margins = 3;
+        let mut rows = None;
+        let mut columns = None;
         if let Some(grid_config) = &config.grid {
             if let Some(width) = grid_config.tile_width {
                 tile_width = width;
@@ -127,6 +354,8 @@ impl From<&Config> for Grid {
             if let Some(margins) = grid_config.margins {
                 grid_margins = margins;
             }
+            rows = grid_config.rows;
+            columns = grid_config.columns;
         }
         let mut grid = Grid {
             zone_margins: config.margins,
@@ -134,9 +363,34 @@ impl From<&Config> for Grid {
             tile_width,
             tile_height,
             grid_margins,
+            profile_name,
+            background_color: color_to_colorref(&config.grid_background()),
+            keys: GridKeyMap::new(config.grid_keys.as_ref()),
+            work_area: monitor.map(|monitor| unsafe { monitor_work_area(monitor) }),
             ..Default::default()
         };
 
+        grid.active_config = match monitor {
+            Some(monitor) => GridConfigKey::for_monitor(config, monitor),
+            None => GridConfigKey::new(config),
+        };
+
+        // Profile dimensions only apply until the grid is resized on this monitor
+        let grid_config = grid
+            .configs
+            .get(&grid.active_config)
+            .cloned()
+            .unwrap_or_else(|| {
+                let default_config = GridConfig::default();
+
+                GridConfig {
+                    rows: rows.unwrap_or(default_config.rows).max(1),
+                    columns: columns.unwrap_or(default_config.columns).max(1),
+                    ..default_config
+                }
+            });
+        grid.tiles = vec![vec![Tile::default(); grid_config.columns]; grid_config.rows];
+
         if let Some(colors) = &config.colors {
             if let Some(color) = &colors.tile {
                 grid.tile_normal_color = color_to_colorref(&color.clone());
@@ -174,7 +428,7 @@ impl From<&Config> for Grid {
 impl Default for Grid {
     fn default() -> Self {
         let configs = GridConfigs::load();
-        let active_config = GridConfigKey::default();
+        let active_config = GridConfigKey::new(&Config::default());
 
         let default_config = configs.get(&active_config).cloned().unwrap_or_default();
 
@@ -182,8 +436,7 @@ impl Default for Grid {
         let columns = default_config.columns;
 
         Grid {
-            shift_down: false,
-            control_down: false,
+            span_down: false,
             cursor_down: false,
             selected_tile: None,
             hovered_tile: None,
@@ -191,6 +444,8 @@ impl Default for Grid {
             grid_window: None,
             previous_resize: None,
             quick_resize: false,
+            keys: GridKeyMap::default(),
+            work_area: None,
             grid_margins: 3,
             zone_margins: 10,
             border_margins: 10,
@@ -199,6 +454,8 @@ impl Default for Grid {
             configs,
             tile_width: 48,
             tile_height: 48,
+            profile_name: String::new(),
+            background_color: color_to_colorref(&[44, 44, 44, 255].into()),
             tile_normal_color: color_to_colorref(&[178, 178, 178, 255].into()),
             tile_hovered_color: color_to_colorref(&[0, 100, 148, 255].into()),
             tile_selected_color: color_to_colorref(&[0, 77, 128, 255].into()),
@@ -209,8 +466,7 @@ impl Default for Grid {
 
 impl Grid {
     pub fn reset(&mut self) {
-        self.shift_down = false;
-        self.control_down = false;
+        self.span_down = false;
         self.cursor_down = false;
         self.selected_tile = None;
         self.hovered_tile = None;
@@ -228,30 +484,47 @@ impl Grid {
     fn save_config(&mut self) {
         let rows = self.rows();
         let columns = self.columns();
+        let last_used = unix_time();
 
         if let Some(grid_config) = self.configs.get_mut(&self.active_config) {
             grid_config.rows = rows;
             grid_config.columns = columns;
+            grid_config.last_used = last_used;
         } else {
-            self.configs
-                .insert(self.active_config.clone(), GridConfig { rows, columns });
+            self.configs.insert(
+                self.active_config.clone(),
+                GridConfig {
+                    rows,
+                    columns,
+                    last_used,
+                },
+            );
         }
 
         self.configs.save();
     }
 
+    /// Marks the cached dimensions for the active monitor and profile as used
+    pub fn touch(&mut self) {
+        if let Some(grid_config) = self.configs.get_mut(&self.active_config) {
+            grid_config.last_used = unix_time();
+            self.configs.save();
+        }
+    }
+
     pub fn dimensions(&self) -> (u32, u32) {
         let width = self.columns() as u32 * self.tile_width
             + (self.columns() as u32 + 1) * self.grid_margins as u32;
 
-        let height = self.rows() as u32 * self.tile_height
+        let height = HEADER_HEIGHT
+            + self.rows() as u32 * self.tile_height
             + (self.rows() as u32 + 1) * self.grid_margins as u32;
 
         (width, height)
     }
 
     fn zone_area(&self, row: usize, column: usize) -> Rect {
-        let work_area = unsafe { get_work_area() };
+        let work_area = self.work_area.unwrap_or_else(|| unsafe { get_work_area() });
 
         let zone_width = (work_area.width
             - self.border_margins as i32 * 2
@@ -279,11 +552,11 @@ impl Grid {
         }
     }
 
-    fn rows(&self) -> usize {
+    pub fn rows(&self) -> usize {
         self.tiles.len()
     }
 
-    fn columns(&self) -> usize {
+    pub fn columns(&self) -> usize {
         self.tiles[0].len()
     }
 
@@ -333,7 +606,9 @@ impl Grid {
         let x =
             column as i32 * self.tile_width as i32 + (column as i32 + 1) * self.grid_margins as i32;
 
-        let y = row as i32 * self.tile_height as i32 + (row as i32 + 1) * self.grid_margins as i32;
+        let y = HEADER_HEIGHT as i32
+            + row as i32 * self.tile_height as i32
+            + (row as i32 + 1) * self.grid_margins as i32;
 
         Rect {
             x,
@@ -354,7 +629,12 @@ impl Grid {
             height: dimensions.1 as i32,
         };
 
-        self.grid_window.as_mut().unwrap().set_pos(rect, None);
+        let grid_window = self.grid_window.as_mut().unwrap();
+        grid_window.set_pos(rect, None);
+
+        unsafe {
+            let _ = InvalidateRect(grid_window.0, None, true);
+        }
     }
 
     /// Returns true if a change in highlighting occured
@@ -389,7 +669,7 @@ impl Grid {
     }
 
     unsafe fn shift_hover_and_calc_rect(&mut self, highlight: bool) -> Option<Rect> {
-        if self.shift_down || self.cursor_down {
+        if self.span_down || self.cursor_down {
             if let Some(selected_tile) = self.selected_tile {
                 if let Some(hovered_tile) = self.hovered_tile {
                     let selected_zone = self.zone_area(selected_tile.0, selected_tile.1);
@@ -465,7 +745,7 @@ impl Grid {
     }
 
     pub unsafe fn select_tile(&mut self, point: (i32, i32)) -> bool {
-        if self.cursor_down || self.shift_down {
+        if self.cursor_down || self.span_down {
             return false;
         }
 
@@ -500,6 +780,40 @@ impl Grid {
         }
     }
 
+    /// Area spanning the zones from `from` to `to`, given as (row, column), or
+    /// None if either is outside the grid
+    pub fn span_area(&self, from: (usize, usize), to: (usize, usize)) -> Option<Rect> {
+        if from.0 > to.0 || from.1 > to.1 || to.0 >= self.rows() || to.1 >= self.columns() {
+            return None;
+        }
+
+        let from_zone = self.zone_area(from.0, from.1);
+        let to_zone = self.zone_area(to.0, to.1);
+
+        Some(Rect {
+            x: from_zone.x,
+            y: from_zone.y,
+            width: (to_zone.x + to_zone.width) - from_zone.x,
+            height: (to_zone.y + to_zone.height) - from_zone.y,
+        })
+    }
+
+    /// Zones covered by the current selection, including the span being dragged or
+    /// extended with the span modifier
+    pub fn selected_span(&self) -> Option<ZoneSpan> {
+        let selected = self.selected_tile?;
+        let other = if self.span_down || self.cursor_down {
+            self.hovered_tile.unwrap_or(selected)
+        } else {
+            selected
+        };
+
+        Some(ZoneSpan {
+            from: (selected.0.min(other.0), selected.1.min(other.1)),
+            to: (selected.0.max(other.0), selected.1.max(other.1)),
+        })
+    }
+
     pub unsafe fn selected_area(&mut self) -> Option<Rect> {
         if let Some(shift_rect) = self.shift_hover_and_calc_rect(false) {
             return Some(shift_rect);
@@ -527,6 +841,19 @@ impl Grid {
 
         let hdc = BeginPaint(window.0, &mut paint);
 
+        let (width, height) = self.dimensions();
+        let background = Rect {
+            x: 0,
+            y: 0,
+            width: width as i32,
+            height: height as i32,
+        };
+        let background_brush = CreateSolidBrush(self.background_color);
+        FillRect(hdc, &background.into(), background_brush);
+        let _ = DeleteObject(background_brush);
+
+        self.draw_header(hdc);
+
         for row in 0..self.rows() {
             for column in 0..self.columns() {
                 self.tiles[row][column].draw(hdc, self.tile_area(row, column));
@@ -535,6 +862,35 @@ impl Grid {
 
         let _ = EndPaint(window.0, &paint);
     }
+
+    unsafe fn draw_header(&self, hdc: HDC) {
+        let (width, _) = self.dimensions();
+        let header = Rect {
+            x: self.grid_margins as i32,
+            y: self.grid_margins as i32,
+            width: width as i32 - self.grid_margins as i32 * 2,
+            height: HEADER_HEIGHT as i32 - self.grid_margins as i32,
+        };
+
+        let mut text = str_to_wide!(self.profile_name);
+        let len = text.len() - 1;
+
+        SetBkMode(hdc, TRANSPARENT);
+        SetTextColor(hdc, self.tile_normal_color);
+        DrawTextW(
+            hdc,
+            &mut text[..len],
+            &mut header.into(),
+            DT_CENTER | DT_VCENTER | DT_SINGLELINE | DT_END_ELLIPSIS,
+        );
+    }
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
 }
 
 #[derive(Default, Clone, Copy, PartialEq)]
@@ -571,3 +927,110 @@ impl Tile {
         CreateSolidBrush(color)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// An empty directory for each test, so tests can run in parallel
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("grout-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn key() -> GridConfigKey {
+        GridConfigKey {
+            monitor: "DEL4105".to_owned(),
+            profile: "Default".to_owned(),
+        }
+    }
+
+    fn configs(rows: usize) -> GridConfigs {
+        let mut configs = HashMap::new();
+        configs.insert(
+            key(),
+            GridConfig {
+                rows,
+                columns: 4,
+                last_used: 1_700_000_000,
+            },
+        );
+        configs
+    }
+
+    #[test]
+    fn save_then_load() {
+        let path = temp_dir("round-trip").join("grid.ron");
+
+        configs(3).save_to(&path).unwrap();
+        let (loaded, version) = GridConfigs::load_from(&path).unwrap();
+
+        assert_eq!(version, GRID_CACHE_VERSION);
+        let grid_config = loaded[&key()];
+        assert_eq!(
+            (grid_config.rows, grid_config.columns, grid_config.last_used),
+            (3, 4, 1_700_000_000)
+        );
+    }
+
+    #[test]
+    fn version_1_is_migrated() {
+        let path = temp_dir("migrate").join("grid.ron");
+        fs::write(
+            &path,
+            r#"(version: 1, configs: {(monitor: "DEL4105", profile: "Default"): (rows: 3, columns: 4)})"#,
+        )
+        .unwrap();
+
+        let (loaded, migrated) = load_cache(&path);
+        assert!(migrated);
+        assert!(loaded[&key()].last_used > 0);
+
+        loaded.save_to(&path).unwrap();
+        let (_, version) = GridConfigs::load_from(&path).unwrap();
+        assert_eq!(version, GRID_CACHE_VERSION);
+    }
+
+    #[test]
+    fn corrupt_cache_falls_back_to_backup() {
+        let path = temp_dir("corrupt").join("grid.ron");
+
+        configs(3).save_to(&path).unwrap();
+        configs(5).save_to(&path).unwrap();
+        fs::write(&path, "(version: 2, configs: {").unwrap();
+
+        let (loaded, restored) = load_cache(&path);
+
+        assert!(restored);
+        assert_eq!(loaded[&key()].rows, 3);
+        assert!(!path.with_extension("ron.corrupt").exists());
+
+        save_upgraded(&loaded, &path).unwrap();
+
+        assert!(path.with_extension("ron.corrupt").exists());
+        let (saved, _) = GridConfigs::load_from(&path).unwrap();
+        assert_eq!(saved[&key()].rows, 3);
+        let (backup, _) = GridConfigs::load_from(&backup_path(&path)).unwrap();
+        assert_eq!(backup[&key()].rows, 3);
+    }
+
+    #[test]
+    fn save_leaves_no_temporary_file() {
+        let dir = temp_dir("atomic");
+        let path = dir.join("grid.ron");
+
+        configs(3).save_to(&path).unwrap();
+        configs(5).save_to(&path).unwrap();
+
+        let mut files = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+
+        assert_eq!(files, ["grid.ron", "grid.ron.bak"]);
+    }
+}
@@ -0,0 +1,178 @@
+use serde_json::Value;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bus;
+use crate::config::Hook;
+use crate::CONFIG;
+
+/// How long a hook command can run when its `timeout_ms` isn't set
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often a running hook command is checked for having exited
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Prefix of the environment variables describing the event, kept apart from the
+/// `GROUT_` config overrides
+const ENV_PREFIX: &str = "GROUT_HOOK_";
+
+/// Runs the `[[hooks]]` commands for each event, each on its own thread so a slow
+/// command never holds up the message loop or other hooks
+pub fn spawn_hook_thread() {
+    thread::spawn(|| {
+        for event in bus::subscribe().iter() {
+            let hooks = CONFIG
+                .lock()
+                .unwrap()
+                .hooks
+                .iter()
+                .flatten()
+                .filter(|hook| hook.event == event.name())
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if hooks.is_empty() {
+                continue;
+            }
+
+            let json = match serde_json::to_value(&event) {
+                Ok(json) => json,
+                Err(e) => {
+                    log::warn!("Failed to serialize {} event: {}", event.name(), e);
+                    continue;
+                }
+            };
+
+            for hook in hooks {
+                let json = json.clone();
+                let name = event.name();
+                thread::spawn(move || run_hook(&hook, name, &json));
+            }
+        }
+    });
+}
+
+fn run_hook(hook: &Hook, event: &str, json: &Value) {
+    let mut command = Command::new(&hook.command);
+    command
+        .args(&hook.args)
+        .env(format!("{}EVENT", ENV_PREFIX), event)
+        .env(format!("{}EVENT_JSON", ENV_PREFIX), json.to_string())
+        .envs(event_variables(json))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    if hook.json_arg {
+        command.arg(json.to_string());
+    }
+
+    // Console programs would otherwise flash a console window
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        use windows::Win32::System::Threading::CREATE_NO_WINDOW;
+
+        command.creation_flags(CREATE_NO_WINDOW.0);
+    }
+
+    let child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            log::warn!("Failed to run {} hook `{}`: {}", event, hook.command, e);
+            return;
+        }
+    };
+
+    let timeout = hook
+        .timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_TIMEOUT);
+
+    wait_for_hook(child, timeout, hook, event);
+}
+
+fn wait_for_hook(mut child: Child, timeout: Duration, hook: &Hook, event: &str) {
+    let started = Instant::now();
+
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return,
+            Ok(Some(status)) => {
+                log::warn!("{} hook `{}` failed: {}", event, hook.command, status);
+                return;
+            }
+            Ok(None) if started.elapsed() >= timeout => {
+                log::warn!(
+                    "{} hook `{}` didn't finish within {} ms, stopping it",
+                    event,
+                    hook.command,
+                    timeout.as_millis()
+                );
+                let _ = child.kill();
+                let _ = child.wait();
+                return;
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                log::warn!(
+                    "Failed to wait for {} hook `{}`: {}",
+                    event,
+                    hook.command,
+                    e
+                );
+                return;
+            }
+        }
+    }
+}
+
+/// The event's fields as environment variables, with nested keys joined by `_`,
+/// e.g. `GROUT_HOOK_WINDOW_RECT_X` for `window.rect.x`. Lists of values are joined
+/// by commas, lists of tables such as `monitors` are only in the JSON.
+fn event_variables(json: &Value) -> Vec<(String, String)> {
+    let mut variables = vec![];
+
+    if let Value::Object(fields) = json {
+        for (key, value) in fields.iter().filter(|(key, _)| *key != "event") {
+            add_variables(&mut variables, &format!("{}{}", ENV_PREFIX, key), value);
+        }
+    }
+
+    variables
+}
+
+fn add_variables(variables: &mut Vec<(String, String)>, name: &str, value: &Value) {
+    let name = name.to_uppercase();
+
+    match value {
+        Value::Null => {}
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                add_variables(variables, &format!("{}_{}", name, key), value);
+            }
+        }
+        Value::Array(values) => {
+            let values = values.iter().map(scalar).collect::<Option<Vec<_>>>();
+
+            if let Some(values) = values {
+                variables.push((name, values.join(",")));
+            }
+        }
+        value => {
+            if let Some(value) = scalar(value) {
+                variables.push((name, value));
+            }
+        }
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
@@ -1,97 +1,973 @@
+use anyhow::format_err;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use std::collections::HashMap;
+use std::fmt;
 use std::mem;
 use std::thread;
 use windows::Win32::{
-    Foundation::HWND,
+    Foundation::{HWND, LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
     UI::{
-        Input::KeyboardAndMouse::{
-            GetKeyboardLayout, RegisterHotKey, VkKeyScanExW, HOT_KEY_MODIFIERS, MOD_ALT,
-            MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN,
+        Input::KeyboardAndMouse::*,
+        WindowsAndMessaging::{
+            DispatchMessageW, GetMessageW, PeekMessageW, PostThreadMessageW, TranslateMessage,
+            PM_NOREMOVE, WM_APP, WM_HOTKEY, WM_USER,
         },
-        WindowsAndMessaging::{DispatchMessageW, GetMessageW, TranslateMessage, WM_HOTKEY},
     },
 };
 
-use crate::common::report_and_exit;
+use crate::action::Action;
+use crate::config::{Config, GridKeys};
 use crate::Message;
+use crate::Result;
 use crate::CHANNEL;
 
-#[derive(PartialEq, Clone, Copy, Debug)]
-pub enum HotkeyType {
-    Main,
-    QuickResize,
-    Maximize,
-    NavigateRight,
-    NavigateLeft,
-    NavigateDown,
-    NavigateUp,
-    Exit,
+/// A parsed hotkey combination, such as `CTRL+ALT+F12` or `LWIN+LEFT`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hotkey {
+    pub modifiers: HOT_KEY_MODIFIERS,
+    /// Left / right specific modifiers that must be held, since `RegisterHotKey`
+    /// only distinguishes the generic modifiers
+    pub sided_modifiers: Vec<VIRTUAL_KEY>,
+    pub vk_code: u32,
 }
 
-pub fn spawn_hotkey_thread(hotkey_str: &str, hotkey_type: HotkeyType) {
-    let mut hotkey: Vec<String> = hotkey_str
+impl Hotkey {
+    /// Checks `vk_code` completes this hotkey with the modifiers currently held.
+    /// `ignored` modifiers may be held as well, such as ones still held from the
+    /// leader key.
+    pub fn pressed(&self, vk_code: u32, ignored: HOT_KEY_MODIFIERS) -> bool {
+        let held = held_modifiers();
+
+        self.vk_code == vk_code
+            && (self.modifiers == held || self.modifiers == held & !ignored)
+            && self.sided_modifiers_held()
+    }
+
+    /// Checks the left / right specific modifiers are held when the hotkey fires
+    pub fn sided_modifiers_held(&self) -> bool {
+        self.sided_modifiers_where(key_down)
+    }
+
+    fn sided_modifiers_where(&self, key_held: fn(VIRTUAL_KEY) -> bool) -> bool {
+        self.sided_modifiers.iter().all(|vk| key_held(*vk))
+    }
+
+    /// Checks both hotkeys need the same left / right specific modifiers, in any order
+    fn same_sides(&self, other: &Hotkey) -> bool {
+        self.sided_modifiers.len() == other.sided_modifiers.len()
+            && self
+                .sided_modifiers
+                .iter()
+                .all(|vk| other.sided_modifiers.contains(vk))
+    }
+}
+
+/// Commands available from the keyboard while the grid is open
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridCommand {
+    Close,
+    AddColumn,
+    RemoveColumn,
+    AddRow,
+    RemoveRow,
+}
+
+/// Keys used inside the grid window, resolved from `[grid_keys]`
+#[derive(Debug, Clone)]
+pub struct GridKeyMap {
+    commands: Vec<(Hotkey, GridCommand)>,
+    span_modifier: (HOT_KEY_MODIFIERS, Option<VIRTUAL_KEY>),
+}
+
+impl GridKeyMap {
+    pub fn new(keys: Option<&GridKeys>) -> Self {
+        let keys = keys.cloned().unwrap_or_default();
+
+        let commands = vec![
+            ("close", keys.close, "ESCAPE", GridCommand::Close),
+            (
+                "add_column",
+                keys.add_column,
+                "CTRL+RIGHT",
+                GridCommand::AddColumn,
+            ),
+            (
+                "remove_column",
+                keys.remove_column,
+                "CTRL+LEFT",
+                GridCommand::RemoveColumn,
+            ),
+            ("add_row", keys.add_row, "CTRL+UP", GridCommand::AddRow),
+            (
+                "remove_row",
+                keys.remove_row,
+                "CTRL+DOWN",
+                GridCommand::RemoveRow,
+            ),
+        ];
+
+        let commands = commands
+            .into_iter()
+            .map(|(config_key, key, default, command)| {
+                let hotkey = key
+                    .and_then(|key| match parse_key_combination(&key) {
+                        Ok(hotkey) => Some(hotkey),
+                        Err(e) => {
+                            log::warn!("Ignoring grid_keys.{}: {}", config_key, e);
+                            None
+                        }
+                    })
+                    .unwrap_or_else(|| {
+                        parse_key_combination(default).expect("invalid default grid key")
+                    });
+
+                (hotkey, command)
+            })
+            .collect();
+
+        let span_modifier = keys
+            .span_modifier
+            .and_then(|name| {
+                let modifier = parse_modifier(&name);
+                if modifier.is_none() {
+                    log::warn!(
+                        "Ignoring grid_keys.span_modifier: Unidentified modifier {}",
+                        name
+                    );
+                }
+                modifier
+            })
+            .unwrap_or((MOD_SHIFT, None));
+
+        GridKeyMap {
+            commands,
+            span_modifier,
+        }
+    }
+
+    /// Command bound to `vk_code` with the modifiers currently held. Holding the
+    /// span modifier doesn't stop commands bound without it, so they still work in
+    /// the middle of a selection.
+    pub fn command(&self, vk_code: u32) -> Option<GridCommand> {
+        self.command_with(vk_code, held_modifiers())
+    }
+
+    fn command_with(&self, vk_code: u32, held: HOT_KEY_MODIFIERS) -> Option<GridCommand> {
+        let without_span = held & !self.span_modifier.0;
+
+        let candidates = || {
+            self.commands
+                .iter()
+                .filter(|(hotkey, _)| hotkey.vk_code == vk_code && hotkey.sided_modifiers_held())
+        };
+
+        // A command bound with the span modifier wins over one bound without it
+        candidates()
+            .find(|(hotkey, _)| hotkey.modifiers == held)
+            .or_else(|| candidates().find(|(hotkey, _)| hotkey.modifiers == without_span))
+            .map(|(_, command)| *command)
+    }
+
+    /// Checks `key`, such as a profile's key, was pressed with the modifiers
+    /// currently held, ignoring the span modifier like commands do
+    pub fn key_pressed(&self, key: &Hotkey, vk_code: u32) -> bool {
+        key.pressed(vk_code, self.span_modifier.0)
+    }
+
+    pub fn span_modifier_held(&self) -> bool {
+        match self.span_modifier {
+            (_, Some(side)) => key_held(side),
+            (modifier, None) => modifier_held(modifier, key_held),
+        }
+    }
+}
+
+impl Default for GridKeyMap {
+    fn default() -> Self {
+        GridKeyMap::new(None)
+    }
+}
+
+fn key_held(vk: VIRTUAL_KEY) -> bool {
+    unsafe { GetKeyState(vk.0 as i32) < 0 }
+}
+
+fn key_down(vk: VIRTUAL_KEY) -> bool {
+    unsafe { GetAsyncKeyState(vk.0 as i32) < 0 }
+}
+
+fn modifier_held(modifier: HOT_KEY_MODIFIERS, key_held: fn(VIRTUAL_KEY) -> bool) -> bool {
+    match modifier {
+        MOD_CONTROL => key_held(VK_CONTROL),
+        MOD_ALT => key_held(VK_MENU),
+        MOD_SHIFT => key_held(VK_SHIFT),
+        MOD_WIN => key_held(VK_LWIN) || key_held(VK_RWIN),
+        _ => false,
+    }
+}
+
+fn modifiers_where(key_held: fn(VIRTUAL_KEY) -> bool) -> HOT_KEY_MODIFIERS {
+    [MOD_CONTROL, MOD_ALT, MOD_SHIFT, MOD_WIN]
+        .iter()
+        .copied()
+        .filter(|modifier| modifier_held(*modifier, key_held))
+        .fold(HOT_KEY_MODIFIERS::default(), |held, modifier| {
+            held | modifier
+        })
+}
+
+/// Modifiers held as of the keyboard message being handled on this thread
+pub fn held_modifiers() -> HOT_KEY_MODIFIERS {
+    modifiers_where(key_held)
+}
+
+/// Modifiers held down right now, from any thread
+pub fn modifiers_down() -> HOT_KEY_MODIFIERS {
+    modifiers_where(key_down)
+}
+
+/// Named keys, matched case-insensitively with underscores and spaces ignored
+static NAMED_KEYS: &[(&str, VIRTUAL_KEY)] = &[
+    // Navigation
+    ("LEFT", VK_LEFT),
+    ("RIGHT", VK_RIGHT),
+    ("UP", VK_UP),
+    ("DOWN", VK_DOWN),
+    ("HOME", VK_HOME),
+    ("END", VK_END),
+    ("PGUP", VK_PRIOR),
+    ("PAGEUP", VK_PRIOR),
+    ("PGDN", VK_NEXT),
+    ("PAGEDOWN", VK_NEXT),
+    ("INS", VK_INSERT),
+    ("INSERT", VK_INSERT),
+    ("DEL", VK_DELETE),
+    ("DELETE", VK_DELETE),
+    // Editing and control
+    ("BACKSPACE", VK_BACK),
+    ("TAB", VK_TAB),
+    ("ENTER", VK_RETURN),
+    ("RETURN", VK_RETURN),
+    ("ESC", VK_ESCAPE),
+    ("ESCAPE", VK_ESCAPE),
+    ("SPACE", VK_SPACE),
+    ("PRINTSCREEN", VK_SNAPSHOT),
+    ("PRTSC", VK_SNAPSHOT),
+    ("PAUSE", VK_PAUSE),
+    ("CAPSLOCK", VK_CAPITAL),
+    ("NUMLOCK", VK_NUMLOCK),
+    ("SCROLLLOCK", VK_SCROLL),
+    ("APPS", VK_APPS),
+    ("MENU", VK_APPS),
+    ("SLEEP", VK_SLEEP),
+    // Numpad
+    ("NUMPAD0", VK_NUMPAD0),
+    ("NUMPAD1", VK_NUMPAD1),
+    ("NUMPAD2", VK_NUMPAD2),
+    ("NUMPAD3", VK_NUMPAD3),
+    ("NUMPAD4", VK_NUMPAD4),
+    ("NUMPAD5", VK_NUMPAD5),
+    ("NUMPAD6", VK_NUMPAD6),
+    ("NUMPAD7", VK_NUMPAD7),
+    ("NUMPAD8", VK_NUMPAD8),
+    ("NUMPAD9", VK_NUMPAD9),
+    ("NUMPADMULTIPLY", VK_MULTIPLY),
+    ("NUMPADADD", VK_ADD),
+    ("NUMPADSEPARATOR", VK_SEPARATOR),
+    ("NUMPADSUBTRACT", VK_SUBTRACT),
+    ("NUMPADDECIMAL", VK_DECIMAL),
+    ("NUMPADDIVIDE", VK_DIVIDE),
+    ("MULTIPLY", VK_MULTIPLY),
+    ("ADD", VK_ADD),
+    ("SUBTRACT", VK_SUBTRACT),
+    ("DECIMAL", VK_DECIMAL),
+    ("DIVIDE", VK_DIVIDE),
+    // Media and browser
+    ("VOLUMEMUTE", VK_VOLUME_MUTE),
+    ("VOLUMEDOWN", VK_VOLUME_DOWN),
+    ("VOLUMEUP", VK_VOLUME_UP),
+    ("MEDIANEXT", VK_MEDIA_NEXT_TRACK),
+    ("MEDIANEXTTRACK", VK_MEDIA_NEXT_TRACK),
+    ("MEDIAPREV", VK_MEDIA_PREV_TRACK),
+    ("MEDIAPREVTRACK", VK_MEDIA_PREV_TRACK),
+    ("MEDIASTOP", VK_MEDIA_STOP),
+    ("MEDIAPLAYPAUSE", VK_MEDIA_PLAY_PAUSE),
+    ("BROWSERBACK", VK_BROWSER_BACK),
+    ("BROWSERFORWARD", VK_BROWSER_FORWARD),
+    ("BROWSERREFRESH", VK_BROWSER_REFRESH),
+    ("BROWSERSTOP", VK_BROWSER_STOP),
+    ("BROWSERSEARCH", VK_BROWSER_SEARCH),
+    ("BROWSERFAVORITES", VK_BROWSER_FAVORITES),
+    ("BROWSERHOME", VK_BROWSER_HOME),
+    ("LAUNCHMAIL", VK_LAUNCH_MAIL),
+    ("LAUNCHMEDIASELECT", VK_LAUNCH_MEDIA_SELECT),
+    ("LAUNCHAPP1", VK_LAUNCH_APP1),
+    ("LAUNCHAPP2", VK_LAUNCH_APP2),
+    // OEM keys, by virtual key name and by their US layout character
+    ("OEM1", VK_OEM_1),
+    ("OEM2", VK_OEM_2),
+    ("OEM3", VK_OEM_3),
+    ("OEM4", VK_OEM_4),
+    ("OEM5", VK_OEM_5),
+    ("OEM6", VK_OEM_6),
+    ("OEM7", VK_OEM_7),
+    ("OEM8", VK_OEM_8),
+    ("OEM102", VK_OEM_102),
+    ("OEMPLUS", VK_OEM_PLUS),
+    ("OEMCOMMA", VK_OEM_COMMA),
+    ("OEMMINUS", VK_OEM_MINUS),
+    ("OEMPERIOD", VK_OEM_PERIOD),
+    // The `=` / `+` key on US layouts, not the number pad's `+`, which is `ADD`
+    ("PLUS", VK_OEM_PLUS),
+    ("COMMA", VK_OEM_COMMA),
+    ("MINUS", VK_OEM_MINUS),
+    ("PERIOD", VK_OEM_PERIOD),
+    ("SEMICOLON", VK_OEM_1),
+    ("SLASH", VK_OEM_2),
+    ("BACKTICK", VK_OEM_3),
+    ("GRAVE", VK_OEM_3),
+    ("LBRACKET", VK_OEM_4),
+    ("BACKSLASH", VK_OEM_5),
+    ("RBRACKET", VK_OEM_6),
+    ("QUOTE", VK_OEM_7),
+];
+
+/// Parses a hotkey combination: one to four modifiers followed by a key, separated by `+`
+pub fn parse_hotkey(hotkey_str: &str) -> Result<Hotkey> {
+    parse_combination(hotkey_str, 1)
+}
+
+/// Parses a key with up to four optional modifiers, such as `ESCAPE` or `CTRL+RIGHT`
+pub fn parse_key_combination(combination: &str) -> Result<Hotkey> {
+    parse_combination(combination, 0)
+}
+
+fn parse_combination(hotkey_str: &str, min_modifiers: usize) -> Result<Hotkey> {
+    let trimmed = hotkey_str.trim();
+
+    // A trailing "++" binds the plus key itself
+    let (modifiers_str, key_str) = if trimmed == "+" {
+        ("", "+")
+    } else if let Some(modifiers) = trimmed.strip_suffix("++") {
+        (modifiers, "+")
+    } else {
+        trimmed.rsplit_once('+').unwrap_or(("", trimmed))
+    };
+
+    let modifier_names = modifiers_str
         .split('+')
-        .map(|s| s.trim().to_string())
-        .collect();
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<_>>();
 
-    if hotkey.len() < 2 || hotkey.len() > 5 {
-        report_and_exit(&format!(
-            "Invalid hotkey <{}>: Combination must be between 2 to 5 keys long.",
-            hotkey_str
+    if modifier_names.len() < min_modifiers || modifier_names.len() > 4 {
+        return Err(format_err!(
+            "Invalid hotkey <{}>: Combination must be between {} to 5 keys long.",
+            hotkey_str,
+            min_modifiers + 1
         ));
     }
 
-    let virtual_key_char = hotkey.pop().unwrap().chars().next().unwrap();
+    let mut modifiers = HOT_KEY_MODIFIERS::default();
+    let mut sided_modifiers = vec![];
+    let mut seen = vec![];
+
+    for name in modifier_names {
+        let (modifier, side) = parse_modifier(name).ok_or_else(|| {
+            format_err!(
+                "Invalid hotkey <{}>: Unidentified modifier {} in hotkey combination. Valid modifiers are CTRL, ALT, SHIFT, WIN and their left / right variants, such as LCTRL or RALT.",
+                hotkey_str,
+                name
+            )
+        })?;
+
+        if seen.contains(&(modifier, side)) {
+            return Err(format_err!(
+                "Invalid hotkey <{}>: Modifier {} is repeated.",
+                hotkey_str,
+                name
+            ));
+        }
+        seen.push((modifier, side));
+
+        modifiers |= modifier;
+        if let Some(side) = side {
+            sided_modifiers.push(side);
+        }
+    }
+
+    let (vk_code, key_modifiers) = resolve_key(key_str.trim()).ok_or_else(|| {
+        format_err!(
+            "Invalid hotkey <{}>: Unidentified key {} in hotkey combination.",
+            hotkey_str,
+            key_str.trim()
+        )
+    })?;
+
+    Ok(Hotkey {
+        modifiers: modifiers | key_modifiers,
+        sided_modifiers,
+        vk_code,
+    })
+}
+
+pub fn parse_modifier(name: &str) -> Option<(HOT_KEY_MODIFIERS, Option<VIRTUAL_KEY>)> {
+    let modifier = match name.to_uppercase().as_str() {
+        "CTRL" | "CONTROL" => (MOD_CONTROL, None),
+        "LCTRL" | "LCONTROL" => (MOD_CONTROL, Some(VK_LCONTROL)),
+        "RCTRL" | "RCONTROL" => (MOD_CONTROL, Some(VK_RCONTROL)),
+        "ALT" => (MOD_ALT, None),
+        "LALT" => (MOD_ALT, Some(VK_LMENU)),
+        "RALT" | "ALTGR" => (MOD_ALT, Some(VK_RMENU)),
+        "SHIFT" => (MOD_SHIFT, None),
+        "LSHIFT" => (MOD_SHIFT, Some(VK_LSHIFT)),
+        "RSHIFT" => (MOD_SHIFT, Some(VK_RSHIFT)),
+        "WIN" => (MOD_WIN, None),
+        "LWIN" => (MOD_WIN, Some(VK_LWIN)),
+        "RWIN" => (MOD_WIN, Some(VK_RWIN)),
+        _ => return None,
+    };
+
+    Some(modifier)
+}
+
+/// Resolves a key to its virtual key code, plus any modifiers needed to type it
+/// on the current keyboard layout
+fn resolve_key(key: &str) -> Option<(u32, HOT_KEY_MODIFIERS)> {
+    let no_modifiers = HOT_KEY_MODIFIERS::default();
+
+    let mut chars = key.chars();
+    if let (Some(key_char), None) = (chars.next(), chars.next()) {
+        // Letters and digits share their virtual key codes with ASCII on every layout
+        if key_char.is_ascii_alphanumeric() {
+            return Some((key_char.to_ascii_uppercase() as u32, no_modifiers));
+        }
+
+        return unsafe { char_to_vkcode(key_char) };
+    }
+
+    let name = key
+        .chars()
+        .filter(|c| *c != '_' && *c != ' ')
+        .collect::<String>()
+        .to_uppercase();
+
+    if let Some(number) = name
+        .strip_prefix('F')
+        .and_then(|number| number.parse::<u32>().ok())
+    {
+        if (1..=24).contains(&number) {
+            return Some((VK_F1.0 as u32 + number - 1, no_modifiers));
+        }
+    }
+
+    // Virtual key codes run from 0x01 to 0xFE
+    if let Some(hex) = name.strip_prefix("0X") {
+        return u8::from_str_radix(hex, 16)
+            .ok()
+            .filter(|vk_code| (0x01..=0xFE).contains(vk_code))
+            .map(|vk_code| (vk_code as u32, no_modifiers));
+    }
+
+    NAMED_KEYS
+        .iter()
+        .find(|(key_name, _)| *key_name == name)
+        .map(|(_, vk)| (vk.0 as u32, no_modifiers))
+}
+
+/// Looks up the key that types `key_char` on the current keyboard layout
+unsafe fn char_to_vkcode(key_char: char) -> Option<(u32, HOT_KEY_MODIFIERS)> {
+    let keyboard_layout = GetKeyboardLayout(0);
+    let vk_code = VkKeyScanExW(key_char as u16, keyboard_layout);
+
+    if vk_code == -1 {
+        return None;
+    }
+
+    let [shift_state, vk_code] = vk_code.to_be_bytes();
+
+    let mut modifiers = HOT_KEY_MODIFIERS::default();
+    if shift_state & 1 != 0 {
+        modifiers |= MOD_SHIFT;
+    }
+    if shift_state & 2 != 0 {
+        modifiers |= MOD_CONTROL;
+    }
+    if shift_state & 4 != 0 {
+        modifiers |= MOD_ALT;
+    }
+
+    Some((vk_code as u32, modifiers))
+}
+
+/// A hotkey from the config, along with the config key it was read from
+#[derive(Debug, Clone)]
+pub struct HotkeyBinding {
+    pub config_key: String,
+    pub hotkey: String,
+    pub action: Action,
+}
+
+/// A hotkey that failed to parse or register
+#[derive(Debug, Clone)]
+pub struct HotkeyError {
+    pub config_key: String,
+    pub hotkey: String,
+    pub error: String,
+}
+
+impl fmt::Display for HotkeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} = \"{}\": {}",
+            self.config_key, self.hotkey, self.error
+        )
+    }
+}
+
+/// Every key binding configured in `config`
+pub fn hotkey_bindings(config: &Config) -> Vec<HotkeyBinding> {
+    config
+        .bindings()
+        .into_iter()
+        .map(|(config_key, binding)| HotkeyBinding {
+            config_key,
+            hotkey: binding.key,
+            action: binding.action,
+        })
+        .collect()
+}
+
+/// A sequence typed after the leader key
+#[derive(Debug, Clone)]
+pub struct LeaderSequence {
+    pub keys: Vec<Hotkey>,
+    /// The sequence as written in the config, shown in the on-screen hint
+    pub label: String,
+    pub action: Action,
+}
+
+/// Sequences configured under `[leader]`, along with any that failed to parse
+pub fn leader_sequences(config: &Config) -> (Vec<LeaderSequence>, Vec<HotkeyError>) {
+    let mut sequences = vec![];
+    let mut errors = vec![];
+
+    let bindings = config
+        .leader
+        .iter()
+        .flat_map(|leader| leader.sequences.iter().flatten());
+
+    for (idx, binding) in bindings.enumerate() {
+        let keys = binding
+            .key
+            .split_whitespace()
+            .map(parse_key_combination)
+            .collect::<Result<Vec<_>>>();
+
+        match keys {
+            Ok(keys) if !keys.is_empty() => sequences.push(LeaderSequence {
+                keys,
+                label: binding.key.clone(),
+                action: binding.action.clone(),
+            }),
+            result => errors.push(HotkeyError {
+                config_key: format!("leader.sequences[{}]", idx),
+                hotkey: binding.key.clone(),
+                error: result
+                    .err()
+                    .map(|e| e.to_string())
+                    .unwrap_or_else(|| "Sequence is empty.".to_owned()),
+            }),
+        }
+    }
+
+    (sequences, errors)
+}
+
+/// Human readable report of hotkeys that couldn't be registered
+pub fn hotkey_error_report(errors: &[HotkeyError]) -> String {
+    let errors = errors
+        .iter()
+        .map(HotkeyError::to_string)
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    format!(
+        "The following hotkeys are disabled. Fix them in the config file, changes are applied when it's saved.\n\n{}",
+        errors
+    )
+}
+
+/// Bindings registered under one hotkey id, differing only in the side of their
+/// modifiers
+type BindingGroup = Vec<(Hotkey, Action)>;
+
+/// Posted to the hotkey thread when new bindings are waiting to be registered
+const WM_REGISTER_HOTKEYS: u32 = WM_APP + 1;
+
+/// Handle to the thread that owns every registered hotkey
+pub struct HotkeyThread {
+    thread_id: u32,
+    bindings: Sender<Vec<HotkeyBinding>>,
+    errors: Receiver<Vec<HotkeyError>>,
+}
+
+impl HotkeyThread {
+    /// Replaces the registered hotkeys with `bindings`, each with its own hotkey id.
+    /// Bindings that fail to parse or register are skipped and returned, so the rest
+    /// keep working.
+    pub fn register(&self, bindings: Vec<HotkeyBinding>) -> Vec<HotkeyError> {
+        if self.bindings.send(bindings).is_err() {
+            return vec![];
+        }
+
+        // Hotkeys belong to the thread that registers them, so wake it to do so
+        let posted = unsafe {
+            PostThreadMessageW(
+                self.thread_id,
+                WM_REGISTER_HOTKEYS,
+                WPARAM::default(),
+                LPARAM::default(),
+            )
+        };
+        if let Err(e) = posted {
+            log::warn!("Failed to wake the hotkey thread: {}", e);
+            return vec![];
+        }
+
+        self.errors.recv().unwrap_or_default()
+    }
+}
+
+/// Spawns the message thread hotkeys are registered on. Hotkeys are registered
+/// with `HotkeyThread::register`.
+pub fn spawn_hotkey_thread() -> HotkeyThread {
+    let (bindings_sender, bindings_receiver) = bounded::<Vec<HotkeyBinding>>(1);
+    let (errors_sender, errors_receiver) = bounded(1);
+    let (thread_id_sender, thread_id_receiver) = bounded(1);
 
-    let hotkey_str = hotkey_str.to_owned();
     thread::spawn(move || unsafe {
         let sender = &CHANNEL.0.clone();
         let hwnd: HWND = Default::default();
 
-        let result = RegisterHotKey(
-            hwnd,
-            0,
-            compile_modifiers(&hotkey, &hotkey_str) | MOD_NOREPEAT,
-            get_vkcode(virtual_key_char),
-        );
-
-        if result.is_err() {
-            report_and_exit(&format!("Failed to assign hot key <{}>. Either program is already running or hotkey is already assigned in another program.", hotkey_str));
-        }
+        let mut registered: HashMap<i32, BindingGroup> = HashMap::new();
 
+        // Messages can only be posted to the thread once it has a message queue,
+        // which peeking creates
         let mut msg = mem::zeroed();
-        let hwnd: HWND = Default::default();
+        let _ = PeekMessageW(&mut msg, hwnd, WM_USER, WM_USER, PM_NOREMOVE);
+        let _ = thread_id_sender.send(GetCurrentThreadId());
+
         while GetMessageW(&mut msg, hwnd, 0, 0).into() {
-            let _ = TranslateMessage(&msg);
-            DispatchMessageW(&msg);
+            match msg.message {
+                WM_HOTKEY => {
+                    let action = registered
+                        .get(&(msg.wParam.0 as i32))
+                        .and_then(|group| held_binding(group, key_down));
 
-            if msg.message == WM_HOTKEY {
-                let _ = sender.send(Message::HotkeyPressed(hotkey_type));
+                    if let Some(action) = action {
+                        let _ = sender.send(Message::Action(action.clone()));
+                    }
+                }
+                WM_REGISTER_HOTKEYS => {
+                    if let Ok(bindings) = bindings_receiver.try_recv() {
+                        for id in registered.keys() {
+                            let _ = UnregisterHotKey(hwnd, *id);
+                        }
+
+                        let (hotkeys, errors) = register_hotkeys(hwnd, bindings);
+                        registered = hotkeys;
+
+                        let _ = errors_sender.send(errors);
+                    }
+                }
+                _ => {
+                    let _ = TranslateMessage(&msg);
+                    DispatchMessageW(&msg);
+                }
             }
         }
     });
+
+    HotkeyThread {
+        thread_id: thread_id_receiver
+            .recv()
+            .expect("hotkey thread exited before starting"),
+        bindings: bindings_sender,
+        errors: errors_receiver,
+    }
 }
 
-fn compile_modifiers(activators: &[String], hotkey_str: &str) -> HOT_KEY_MODIFIERS {
-    let mut code: HOT_KEY_MODIFIERS = Default::default();
-    for key in activators {
-        match key.as_str() {
-            "ALT" => code |= MOD_ALT,
-            "CTRL" => code |= MOD_CONTROL,
-            "SHIFT" => code |= MOD_SHIFT,
-            "WIN" => code |= MOD_WIN,
-            _ => report_and_exit(&format!("Invalid hotkey <{}>: Unidentified modifier in hotkey combination. Valid modifiers are CTRL, ALT, SHIFT, WIN.", hotkey_str))
+/// Registers each combination in `bindings` once, under the id its group of
+/// bindings is returned with
+unsafe fn register_hotkeys(
+    hwnd: HWND,
+    bindings: Vec<HotkeyBinding>,
+) -> (HashMap<i32, BindingGroup>, Vec<HotkeyError>) {
+    let (groups, mut errors) = group_bindings(bindings);
+    let mut registered = HashMap::new();
+
+    for (idx, group) in groups.into_iter().enumerate() {
+        let id = idx as i32 + 1;
+        let (modifiers, vk_code) = (group[0].0.modifiers, group[0].0.vk_code);
+
+        if RegisterHotKey(hwnd, id, modifiers | MOD_NOREPEAT, vk_code).is_err() {
+            for (_, binding) in group {
+                errors.push(HotkeyError {
+                    config_key: binding.config_key,
+                    hotkey: binding.hotkey,
+                    error: "Hotkey is already assigned in another program, or program is already running."
+                        .to_owned(),
+                });
+            }
+            continue;
         }
+
+        let group = group
+            .into_iter()
+            .map(|(hotkey, binding)| (hotkey, binding.action))
+            .collect();
+        registered.insert(id, group);
+    }
+
+    for error in &errors {
+        log::warn!("Failed to register hotkey {}", error);
     }
-    code
+
+    (registered, errors)
 }
 
-unsafe fn get_vkcode(key_char: char) -> u32 {
-    let keyboard_layout = GetKeyboardLayout(0);
-    let vk_code = VkKeyScanExW(key_char as u16, keyboard_layout);
+/// Parses `bindings` and groups them by the combination `RegisterHotKey` sees,
+/// which leaves out the side of modifiers, so `LCTRL+X` and `RCTRL+X` share one
+/// registration
+fn group_bindings(
+    bindings: Vec<HotkeyBinding>,
+) -> (Vec<Vec<(Hotkey, HotkeyBinding)>>, Vec<HotkeyError>) {
+    let mut groups: Vec<Vec<(Hotkey, HotkeyBinding)>> = vec![];
+    let mut errors = vec![];
 
-    if vk_code == -1 {
-        report_and_exit(&format!("Invalid key {} in hotkey combination.", key_char));
+    for binding in bindings {
+        let hotkey = match parse_hotkey(&binding.hotkey) {
+            Ok(hotkey) => hotkey,
+            Err(e) => {
+                errors.push(HotkeyError {
+                    config_key: binding.config_key,
+                    hotkey: binding.hotkey,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        let group = groups.iter_mut().find(|group| {
+            group[0].0.modifiers == hotkey.modifiers && group[0].0.vk_code == hotkey.vk_code
+        });
+
+        match group {
+            Some(group) => {
+                if let Some((_, other)) = group.iter().find(|(bound, _)| bound.same_sides(&hotkey))
+                {
+                    errors.push(HotkeyError {
+                        error: format!(
+                            "Hotkey is already bound to {} in the config.",
+                            other.config_key
+                        ),
+                        config_key: binding.config_key,
+                        hotkey: binding.hotkey,
+                    });
+                    continue;
+                }
+
+                group.push((hotkey, binding));
+            }
+            None => groups.push(vec![(hotkey, binding)]),
+        }
+    }
+
+    (groups, errors)
+}
+
+/// Action of the binding in `group` whose left / right specific modifiers are held,
+/// preferring the one that needs the most, such as `LCTRL+X` over `CTRL+X`
+fn held_binding(group: &[(Hotkey, Action)], key_held: fn(VIRTUAL_KEY) -> bool) -> Option<&Action> {
+    group
+        .iter()
+        .filter(|(hotkey, _)| hotkey.sided_modifiers_where(key_held))
+        .max_by_key(|(hotkey, _)| hotkey.sided_modifiers.len())
+        .map(|(_, action)| action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Direction;
+
+    fn parse_key(key: &str) -> Option<u32> {
+        resolve_key(key.trim()).map(|(vk_code, _)| vk_code)
+    }
+
+    #[test]
+    fn named_keys() {
+        assert_eq!(parse_key("LEFT"), Some(VK_LEFT.0 as u32));
+        assert_eq!(parse_key("escape"), Some(VK_ESCAPE.0 as u32));
+        assert_eq!(parse_key("Page_Down"), Some(VK_NEXT.0 as u32));
+        assert_eq!(parse_key("num pad 5"), Some(VK_NUMPAD5.0 as u32));
+        assert_eq!(parse_key("NOTAKEY"), None);
+    }
+
+    #[test]
+    fn letters_and_digits() {
+        let hotkey = parse_hotkey("ctrl+alt+s").unwrap();
+        assert_eq!(hotkey.modifiers, MOD_CONTROL | MOD_ALT);
+        assert_eq!(hotkey.vk_code, 'S' as u32);
+        assert!(hotkey.sided_modifiers.is_empty());
+
+        let hotkey = parse_hotkey("WIN+1").unwrap();
+        assert_eq!(hotkey.modifiers, MOD_WIN);
+        assert_eq!(hotkey.vk_code, '1' as u32);
+    }
+
+    #[test]
+    fn function_keys() {
+        assert_eq!(parse_key("F1"), Some(VK_F1.0 as u32));
+        assert_eq!(parse_key("f12"), Some(VK_F12.0 as u32));
+        assert_eq!(parse_key("F24"), Some(VK_F24.0 as u32));
+        assert_eq!(parse_key("F0"), None);
+        assert_eq!(parse_key("F25"), None);
+    }
+
+    #[test]
+    fn key_codes() {
+        assert_eq!(parse_key("0x41"), Some(0x41));
+        assert_eq!(parse_key("0XbB"), Some(0xBB));
+        assert_eq!(parse_key("0x100"), None);
+        assert_eq!(parse_key("0x00"), None);
+        assert_eq!(parse_key("0xFF"), None);
+        assert!(parse_hotkey("CTRL+0x0").is_err());
+        assert_eq!(parse_key("0xZZ"), None);
+    }
+
+    #[test]
+    fn plus_key() {
+        let hotkey = parse_hotkey("CTRL+PLUS").unwrap();
+        assert_eq!(hotkey.vk_code, VK_OEM_PLUS.0 as u32);
+        assert_eq!(hotkey.modifiers, MOD_CONTROL);
+
+        let hotkey = parse_key_combination("NUMPADADD").unwrap();
+        assert_eq!(hotkey.vk_code, VK_ADD.0 as u32);
+    }
+
+    #[test]
+    fn sided_modifiers() {
+        let hotkey = parse_hotkey("LCTRL+RALT+X").unwrap();
+        assert_eq!(hotkey.modifiers, MOD_CONTROL | MOD_ALT);
+        assert_eq!(hotkey.sided_modifiers, vec![VK_LCONTROL, VK_RMENU]);
+
+        let hotkey = parse_hotkey("LSHIFT+RSHIFT+X").unwrap();
+        assert_eq!(hotkey.modifiers, MOD_SHIFT);
+        assert_eq!(hotkey.sided_modifiers, vec![VK_LSHIFT, VK_RSHIFT]);
+    }
+
+    #[test]
+    fn duplicate_or_unknown_modifiers() {
+        assert!(parse_hotkey("CTRL+ctrl+S").is_err());
+        assert!(parse_hotkey("LWIN+LWIN+S").is_err());
+
+        let error = parse_hotkey("HYPER+S").unwrap_err().to_string();
+        assert!(error.contains("Unidentified modifier HYPER"), "{}", error);
+
+        assert!(parse_hotkey("CTRL+ALT+SHIFT+WIN+LCTRL+S").is_err());
+    }
+
+    fn binding(config_key: &str, hotkey: &str, action: Action) -> HotkeyBinding {
+        HotkeyBinding {
+            config_key: config_key.to_owned(),
+            hotkey: hotkey.to_owned(),
+            action,
+        }
+    }
+
+    #[test]
+    fn sided_bindings_on_one_key() {
+        let focus = Action::Focus {
+            direction: Direction::Left,
+        };
+        let (groups, errors) = group_bindings(vec![
+            binding("bindings[0]", "LCTRL+X", Action::Maximize),
+            binding("bindings[1]", "RCTRL+X", focus.clone()),
+            binding("bindings[2]", "CTRL+X", Action::Leader),
+            binding("bindings[3]", "lctrl+x", Action::Maximize),
+        ]);
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].config_key, "bindings[3]");
+        assert!(
+            errors[0].error.contains("bindings[0]"),
+            "{}",
+            errors[0].error
+        );
+
+        let group = groups
+            .into_iter()
+            .next()
+            .unwrap()
+            .into_iter()
+            .map(|(hotkey, binding)| (hotkey, binding.action))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            held_binding(&group, |vk| vk == VK_LCONTROL),
+            Some(&Action::Maximize)
+        );
+        assert_eq!(held_binding(&group, |vk| vk == VK_RCONTROL), Some(&focus));
+        assert_eq!(held_binding(&group, |_| false), Some(&Action::Leader));
     }
 
-    vk_code.to_be_bytes()[1] as u32
+    fn grid_keys(add_column: &str) -> GridKeyMap {
+        GridKeyMap::new(Some(&GridKeys {
+            add_column: Some(add_column.to_owned()),
+            span_modifier: Some("SHIFT".to_owned()),
+            ..GridKeys::default()
+        }))
+    }
+
+    #[test]
+    fn grid_command_with_span_modifier() {
+        let keys = grid_keys("SHIFT+RIGHT");
+        let right = VK_RIGHT.0 as u32;
+
+        assert_eq!(
+            keys.command_with(right, MOD_SHIFT),
+            Some(GridCommand::AddColumn)
+        );
+        assert_eq!(keys.command_with(right, HOT_KEY_MODIFIERS::default()), None);
+    }
+
+    #[test]
+    fn grid_command_while_spanning() {
+        let keys = grid_keys("CTRL+RIGHT");
+        let right = VK_RIGHT.0 as u32;
+
+        assert_eq!(
+            keys.command_with(right, MOD_CONTROL | MOD_SHIFT),
+            Some(GridCommand::AddColumn)
+        );
+        assert_eq!(
+            keys.command_with(right, MOD_CONTROL),
+            Some(GridCommand::AddColumn)
+        );
+        assert_eq!(keys.command_with(right, MOD_SHIFT), None);
+    }
+
+    #[test]
+    fn empty_input() {
+        assert!(parse_hotkey("").is_err());
+        assert!(parse_key_combination("").is_err());
+        assert!(parse_key_combination("   ").is_err());
+        assert!(parse_hotkey("CTRL+").is_err());
+        assert_eq!(parse_key(""), None);
+    }
 }
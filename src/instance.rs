@@ -0,0 +1,69 @@
+use anyhow::format_err;
+use windows::{
+    core::{w, PCWSTR},
+    Win32::{
+        Foundation::{
+            CloseHandle, GetLastError, ERROR_ALREADY_EXISTS, HANDLE, WAIT_ABANDONED, WAIT_OBJECT_0,
+        },
+        System::Threading::{CreateMutexW, ReleaseMutex, WaitForSingleObject},
+    },
+};
+
+use crate::ipc::{self, Command};
+use crate::Result;
+
+/// Held by the running instance for as long as it runs. `Local\` scopes it to
+/// the current login session.
+const INSTANCE_MUTEX: PCWSTR = w!(r"Local\grout-instance");
+
+/// How long `--replace` waits for the running instance to exit
+const REPLACE_TIMEOUT_MS: u32 = 5000;
+
+/// Ownership of the instance mutex, released when dropped
+pub struct InstanceLock(HANDLE);
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = ReleaseMutex(self.0);
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
+
+/// Makes this the running instance. Returns `None` if another instance is
+/// already running, unless `replace` is set, in which case that instance is
+/// asked to exit and this one takes over once it has.
+pub fn lock_instance(replace: bool) -> Result<Option<InstanceLock>> {
+    let handle = unsafe { CreateMutexW(None, true, INSTANCE_MUTEX)? };
+
+    if unsafe { GetLastError() } != ERROR_ALREADY_EXISTS {
+        return Ok(Some(InstanceLock(handle)));
+    }
+
+    if !replace {
+        unsafe {
+            let _ = CloseHandle(handle);
+        }
+        return Ok(None);
+    }
+
+    // The running instance may still be starting up and not be listening yet, in
+    // which case there's nothing to send the request to but it's still worth
+    // waiting for
+    if let Err(e) = ipc::send(Command::Quit) {
+        log::warn!("Failed to ask the running instance to exit: {}", e);
+    }
+
+    // The mutex is abandoned rather than released if the instance exits without
+    // dropping its lock, which still hands ownership over
+    match unsafe { WaitForSingleObject(handle, REPLACE_TIMEOUT_MS) } {
+        WAIT_OBJECT_0 | WAIT_ABANDONED => Ok(Some(InstanceLock(handle))),
+        _ => {
+            unsafe {
+                let _ = CloseHandle(handle);
+            }
+            Err(format_err!("The running instance didn't exit"))
+        }
+    }
+}
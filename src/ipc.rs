@@ -0,0 +1,549 @@
+use anyhow::format_err;
+use crossbeam_channel::{bounded, Receiver};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::thread;
+use std::time::Duration;
+use windows::Win32::Foundation::HWND;
+
+use crate::action::Action;
+use crate::bus::{self, Event, EVENT_NAMES};
+use crate::profile::current_profile;
+use crate::tray;
+use crate::Result;
+use crate::{Message, CHANNEL, GRID};
+
+mod pipe;
+
+pub use pipe::NamedPipe;
+
+/// Version of the control protocol described in `docs/ipc.md`. Bumped when a
+/// change would break existing clients.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// How long a command waits to connect to the running instance and for its
+/// response, longer than a busy pipe takes to accept
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A local, connection oriented channel the control server listens on
+pub trait Transport: Send + 'static {
+    type Stream: Read + Write + Send + 'static;
+
+    /// Waits for the next client to connect
+    fn accept(&mut self) -> io::Result<Self::Stream>;
+}
+
+/// One line of JSON sent by a client
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Request {
+    /// Protocol version the client was written for, the current one if missing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<u32>,
+    /// Echoed back in the response so clients can match them up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<Value>,
+    #[serde(flatten)]
+    command: Command,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Command {
+    /// Returns the protocol and program versions
+    Hello,
+    /// Runs an action, as if its key binding was pressed
+    Action(Action),
+    /// Closes the grid if it's open
+    CloseGrid,
+    /// Reloads the config files
+    ReloadConfig,
+    /// Opens the config file in its associated program
+    OpenConfig,
+    /// Exits grout
+    Quit,
+    /// Returns the active profile and whether the grid is open
+    Status,
+    /// Streams events to the client until it disconnects, only those named in
+    /// `events` if it isn't empty
+    Subscribe {
+        #[serde(default)]
+        events: Vec<String>,
+    },
+}
+
+/// Events a client subscribed to, sent after the response to `subscribe`
+struct Subscription {
+    receiver: Receiver<Event>,
+    events: Vec<String>,
+}
+
+impl Subscription {
+    fn wants(&self, event: &Event) -> bool {
+        self.events.is_empty() || self.events.iter().any(|name| name == event.name())
+    }
+}
+
+/// One line of JSON sent back for each request
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Response {
+    version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<Value>,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Response {
+    fn ok(id: Option<Value>, result: Option<Value>) -> Self {
+        Response {
+            version: PROTOCOL_VERSION,
+            id,
+            ok: true,
+            result,
+            error: None,
+        }
+    }
+
+    fn error(id: Option<Value>, error: String) -> Self {
+        Response {
+            version: PROTOCOL_VERSION,
+            id,
+            ok: false,
+            result: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// Sends `command` to the running instance over its pipe. Returns false if there
+/// is no running instance, and fails if it doesn't respond within
+/// `RESPONSE_TIMEOUT`.
+pub fn send(command: Command) -> Result<bool> {
+    let (sender, receiver) = bounded(1);
+
+    // Pipe reads can't time out, so the exchange runs on its own thread, which is
+    // left waiting if the instance is hung
+    thread::spawn(move || {
+        let _ = sender.send(exchange(command));
+    });
+
+    match receiver.recv_timeout(RESPONSE_TIMEOUT) {
+        Ok(result) => result,
+        Err(_) => Err(format_err!("The running instance didn't respond")),
+    }
+}
+
+fn exchange(command: Command) -> Result<bool> {
+    let stream = match NamedPipe::new().connect() {
+        Ok(stream) => stream,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+
+    let request = Request {
+        version: Some(PROTOCOL_VERSION),
+        id: None,
+        command,
+    };
+
+    let mut reader = BufReader::new(stream);
+    write_line(reader.get_mut(), &request)?;
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+
+    let response = serde_json::from_str::<Response>(&line)?;
+    match response.error {
+        Some(error) if !response.ok => Err(format_err!("{}", error)),
+        _ => Ok(true),
+    }
+}
+
+/// Accepts clients on `transport` until it fails, serving each on its own thread
+pub fn spawn_ipc_server<T: Transport>(mut transport: T) {
+    thread::spawn(move || loop {
+        match transport.accept() {
+            Ok(stream) => {
+                thread::spawn(move || {
+                    if let Err(e) = serve(stream) {
+                        log::debug!("IPC client disconnected: {}", e);
+                    }
+                });
+            }
+            Err(e) => {
+                log::error!("IPC server stopped: {}", e);
+                break;
+            }
+        }
+    });
+}
+
+/// Answers each line received on `stream` until the client disconnects or
+/// subscribes to events
+fn serve<S: Read + Write>(stream: S) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (response, subscription) = handle_line(&line);
+
+        let stream = reader.get_mut();
+        write_line(stream, &response)?;
+
+        if let Some(subscription) = subscription {
+            return stream_events(stream, subscription);
+        }
+    }
+}
+
+fn write_line<S: Write, T: Serialize>(stream: &mut S, value: &T) -> io::Result<()> {
+    serde_json::to_writer(&mut *stream, value)?;
+    stream.write_all(b"\n")?;
+    stream.flush()
+}
+
+/// Sends each subscribed event as a line of JSON until writing fails because the
+/// client disconnected
+fn stream_events<S: Write>(stream: &mut S, subscription: Subscription) -> io::Result<()> {
+    for event in subscription.receiver.iter() {
+        if !subscription.wants(&event) {
+            continue;
+        }
+
+        let mut value = serde_json::to_value(&event)?;
+        value["version"] = json!(PROTOCOL_VERSION);
+
+        write_line(stream, &value)?;
+    }
+
+    Ok(())
+}
+
+fn handle_line(line: &str) -> (Response, Option<Subscription>) {
+    let request = match serde_json::from_str::<Request>(line) {
+        Ok(request) => request,
+        Err(e) => {
+            // Still echo the id if the rest of the request was invalid
+            let id = serde_json::from_str::<Value>(line)
+                .ok()
+                .and_then(|value| value.get("id").cloned());
+
+            return (Response::error(id, format!("Invalid request: {}", e)), None);
+        }
+    };
+
+    let version = request.version.unwrap_or(PROTOCOL_VERSION);
+    if version > PROTOCOL_VERSION {
+        let error = format!(
+            "Unsupported protocol version {}, the highest supported is {}",
+            version, PROTOCOL_VERSION
+        );
+
+        return (Response::error(request.id, error), None);
+    }
+
+    match request.command {
+        Command::Subscribe { events } => subscribe(events, request.id),
+        command => (handle_command(command, request.id), None),
+    }
+}
+
+fn subscribe(events: Vec<String>, id: Option<Value>) -> (Response, Option<Subscription>) {
+    if let Some(unknown) = events
+        .iter()
+        .find(|name| !EVENT_NAMES.contains(&name.as_str()))
+    {
+        let error = format!(
+            "Unknown event {}, expected one of {}",
+            unknown,
+            EVENT_NAMES.join(", ")
+        );
+
+        return (Response::error(id, error), None);
+    }
+
+    // Subscribed before responding so no event after the response is missed
+    let subscription = Subscription {
+        receiver: bus::subscribe(),
+        events,
+    };
+
+    (Response::ok(id, None), Some(subscription))
+}
+
+fn handle_command(command: Command, id: Option<Value>) -> Response {
+    let message = match command {
+        Command::Hello => {
+            return Response::ok(
+                id,
+                Some(json!({
+                    "protocol_version": PROTOCOL_VERSION,
+                    "grout_version": env!("CARGO_PKG_VERSION"),
+                })),
+            );
+        }
+        Command::Status => {
+            let grid_open = GRID.lock().unwrap().grid_window.is_some();
+
+            return Response::ok(
+                id,
+                Some(json!({
+                    "profile": current_profile(),
+                    "grid_open": grid_open,
+                })),
+            );
+        }
+        Command::Action(action) => Message::Action(action),
+        Command::CloseGrid => Message::CloseWindows,
+        Command::ReloadConfig => Message::ConfigChange,
+        Command::OpenConfig => {
+            unsafe { tray::open_config(HWND::default()) };
+            return Response::ok(id, None);
+        }
+        Command::Quit => {
+            tray::quit();
+            return Response::ok(id, None);
+        }
+        Command::Subscribe { .. } => unreachable!("handled by handle_line"),
+    };
+
+    match CHANNEL.0.send(message) {
+        Ok(()) => Response::ok(id, None),
+        Err(_) => Response::error(id, "grout is shutting down".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::{unbounded, Sender};
+    use std::io::Cursor;
+
+    /// Transport whose clients connect through in-memory channels, so the protocol
+    /// can be tested on any platform
+    struct MemoryTransport(Receiver<MemoryStream>);
+
+    impl Transport for MemoryTransport {
+        type Stream = MemoryStream;
+
+        fn accept(&mut self) -> io::Result<MemoryStream> {
+            self.0
+                .recv()
+                .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "transport closed"))
+        }
+    }
+
+    /// One end of an in-memory connection
+    struct MemoryStream {
+        incoming: Receiver<Vec<u8>>,
+        outgoing: Sender<Vec<u8>>,
+        unread: Cursor<Vec<u8>>,
+    }
+
+    impl MemoryStream {
+        fn pair() -> (MemoryStream, MemoryStream) {
+            let (to_first, from_second) = unbounded();
+            let (to_second, from_first) = unbounded();
+
+            let first = MemoryStream {
+                incoming: from_second,
+                outgoing: to_second,
+                unread: Cursor::default(),
+            };
+            let second = MemoryStream {
+                incoming: from_first,
+                outgoing: to_first,
+                unread: Cursor::default(),
+            };
+
+            (first, second)
+        }
+    }
+
+    impl Read for MemoryStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.unread.position() as usize == self.unread.get_ref().len() {
+                match self.incoming.recv() {
+                    Ok(bytes) => self.unread = Cursor::new(bytes),
+                    // The other end disconnected
+                    Err(_) => return Ok(0),
+                }
+            }
+
+            self.unread.read(buf)
+        }
+    }
+
+    impl Write for MemoryStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.outgoing
+                .send(buf.to_vec())
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Starts a server on its own in-memory transport and connects a client to it
+    fn memory_client() -> BufReader<MemoryStream> {
+        let (clients, accepted) = unbounded();
+        spawn_ipc_server(MemoryTransport(accepted));
+
+        let (client, server) = MemoryStream::pair();
+        clients.send(server).unwrap();
+
+        BufReader::new(client)
+    }
+
+    fn request<S: Read + Write>(stream: &mut BufReader<S>, request: &str) -> Response {
+        let client = stream.get_mut();
+        client.write_all(request.as_bytes()).unwrap();
+        client.write_all(b"\n").unwrap();
+
+        let mut line = String::new();
+        stream.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[test]
+    fn hello() {
+        let mut client = memory_client();
+
+        let response = request(&mut client, r#"{"id": 1, "command": "hello"}"#);
+        assert!(response.ok);
+        assert_eq!(response.id, Some(json!(1)));
+        assert_eq!(
+            response.result.unwrap()["protocol_version"],
+            json!(PROTOCOL_VERSION)
+        );
+
+        // Blank lines are skipped rather than answered
+        let response = request(&mut client, "\n{\"version\": 1, \"command\": \"hello\"}");
+        assert!(response.ok);
+        assert_eq!(response.id, None);
+    }
+
+    #[test]
+    fn invalid_requests() {
+        let mut client = memory_client();
+
+        let response = request(&mut client, r#"{"id": "a", "command": "unknown"}"#);
+        assert!(!response.ok);
+        assert_eq!(response.id, Some(json!("a")));
+        assert!(response.error.unwrap().starts_with("Invalid request"));
+
+        let response = request(&mut client, "not json");
+        assert!(!response.ok);
+        assert_eq!(response.id, None);
+
+        // The connection stays usable after a bad request
+        let response = request(&mut client, r#"{"command": "hello"}"#);
+        assert!(response.ok);
+    }
+
+    #[test]
+    fn newer_protocol_version() {
+        let mut client = memory_client();
+
+        let response = request(
+            &mut client,
+            r#"{"id": 2, "version": 99, "command": "hello"}"#,
+        );
+        assert!(!response.ok);
+        assert_eq!(response.id, Some(json!(2)));
+        assert!(response
+            .error
+            .unwrap()
+            .contains("Unsupported protocol version 99"));
+    }
+
+    #[test]
+    fn subscription() {
+        let mut client = memory_client();
+
+        let response = request(
+            &mut client,
+            r#"{"command": "subscribe", "events": ["no-such-event"]}"#,
+        );
+        assert!(!response.ok);
+        assert!(response
+            .error
+            .unwrap()
+            .contains("Unknown event no-such-event"));
+
+        let response = request(
+            &mut client,
+            r#"{"command": "subscribe", "events": ["grid-closed"]}"#,
+        );
+        assert!(response.ok);
+
+        bus::publish(Event::ProfileChanged {
+            profile: "Default".to_string(),
+        });
+        bus::publish(Event::GridClosed);
+
+        let mut line = String::new();
+        client.read_line(&mut line).unwrap();
+        let event = serde_json::from_str::<Value>(&line).unwrap();
+        assert_eq!(event["event"], json!("grid-closed"));
+        assert_eq!(event["version"], json!(PROTOCOL_VERSION));
+    }
+
+    /// Opens the client end of `pipe`, waiting for the server thread to create it
+    #[cfg(windows)]
+    fn connect(pipe: &NamedPipe) -> std::fs::File {
+        use std::time::Instant;
+
+        let started = Instant::now();
+
+        loop {
+            match pipe.connect() {
+                Ok(client) => return client,
+                Err(e) if started.elapsed() > Duration::from_secs(5) => {
+                    panic!("Failed to connect: {}", e)
+                }
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn named_pipe_client() {
+        let name = format!("grout-test-{}", std::process::id());
+
+        spawn_ipc_server(NamedPipe::with_name(&name));
+        let mut client = BufReader::new(connect(&NamedPipe::with_name(&name)));
+
+        let response = request(&mut client, r#"{"id": 1, "command": "hello"}"#);
+        assert!(response.ok);
+        assert_eq!(response.id, Some(json!(1)));
+
+        let response = request(
+            &mut client,
+            r#"{"command": "action", "action": "focus", "direction": "left"}"#,
+        );
+        assert!(response.ok);
+        assert!(matches!(
+            CHANNEL.1.try_recv(),
+            Ok(Message::Action(Action::Focus { .. }))
+        ));
+    }
+}
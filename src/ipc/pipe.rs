@@ -0,0 +1,151 @@
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::{
+            CloseHandle, ERROR_BROKEN_PIPE, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED, HANDLE,
+            INVALID_HANDLE_VALUE,
+        },
+        Storage::FileSystem::{FlushFileBuffers, ReadFile, WriteFile, PIPE_ACCESS_DUPLEX},
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, WaitNamedPipeW,
+            PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE,
+            PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+        },
+    },
+};
+
+use super::Transport;
+use crate::str_to_wide;
+
+const BUFFER_SIZE: u32 = 4096;
+
+/// How long a client waits for a busy server to accept it
+const CONNECT_TIMEOUT_MS: u32 = 5000;
+
+/// Named pipe the control server listens on, `\\.\pipe\grout-<user name>`
+pub struct NamedPipe {
+    name: String,
+}
+
+impl NamedPipe {
+    pub fn new() -> Self {
+        let user = env::var("USERNAME").unwrap_or_default();
+
+        NamedPipe::with_name(&format!("grout-{}", user))
+    }
+
+    /// Pipe named `\\.\pipe\<name>`
+    pub fn with_name(name: &str) -> Self {
+        NamedPipe {
+            name: format!(r"\\.\pipe\{}", name),
+        }
+    }
+}
+
+impl NamedPipe {
+    /// Connects to the server listening on the pipe. Fails with
+    /// `io::ErrorKind::NotFound` if there is none, and waits up to
+    /// `CONNECT_TIMEOUT_MS` for a busy server to accept another client.
+    pub fn connect(&self) -> io::Result<File> {
+        loop {
+            match OpenOptions::new().read(true).write(true).open(&self.name) {
+                Ok(file) => return Ok(file),
+                Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) => {
+                    let name = str_to_wide!(self.name);
+
+                    if !unsafe { WaitNamedPipeW(PCWSTR(name.as_ptr()), CONNECT_TIMEOUT_MS) }
+                        .as_bool()
+                    {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "the running instance isn't accepting connections",
+                        ));
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Transport for NamedPipe {
+    type Stream = PipeStream;
+
+    fn accept(&mut self) -> io::Result<PipeStream> {
+        let name = str_to_wide!(self.name);
+
+        let handle = unsafe {
+            CreateNamedPipeW(
+                PCWSTR(name.as_ptr()),
+                PIPE_ACCESS_DUPLEX,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                None,
+            )
+        };
+
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+
+        let stream = PipeStream(handle);
+
+        // A client connecting between creating the pipe and waiting reports an error
+        // but is connected
+        match unsafe { ConnectNamedPipe(handle, None) } {
+            Ok(()) => Ok(stream),
+            Err(e) if e.code() == ERROR_PIPE_CONNECTED.to_hresult() => Ok(stream),
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+}
+
+/// Server end of a connected pipe instance, closed when dropped
+pub struct PipeStream(HANDLE);
+
+// The handle is only used by the thread serving the client
+unsafe impl Send for PipeStream {}
+
+impl Read for PipeStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+
+        match unsafe { ReadFile(self.0, Some(buf), Some(&mut read), None) } {
+            Ok(()) => Ok(read as usize),
+            // The client closing its end reads as the end of the stream
+            Err(e) if e.code() == ERROR_BROKEN_PIPE.to_hresult() => Ok(0),
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+}
+
+impl Write for PipeStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+
+        unsafe { WriteFile(self.0, Some(buf), Some(&mut written), None) }
+            .map_err(io::Error::other)?;
+
+        Ok(written as usize)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        unsafe { FlushFileBuffers(self.0) }.map_err(io::Error::other)
+    }
+}
+
+impl Drop for PipeStream {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = FlushFileBuffers(self.0);
+            let _ = DisconnectNamedPipe(self.0);
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use log::kv::{self, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fmt::{self, Write as _};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::result;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use windows::Win32::Foundation::HWND;
+
+use crate::common::{window_exe, window_title};
+use crate::config;
+use crate::Result;
+
+/// The log is rotated once it grows past this many bytes
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+/// Rotated logs kept, `grout.log.1` being the newest
+const ROTATED_LOGS: usize = 3;
+
+const LOG_DIR: &str = "logs";
+const LOG_FILE: &str = "grout.log";
+
+static LOGGER: Logger = Logger {
+    file: Mutex::new(None),
+};
+
+/// Writes each record to stderr, and to the log file once `log_to_file` is called
+struct Logger {
+    file: Mutex<Option<LogFile>>,
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(path: PathBuf) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(LogFile { path, file, size })
+    }
+
+    fn write(&mut self, line: &str) {
+        if self.size + line.len() as u64 > MAX_LOG_SIZE {
+            if let Err(e) = self.rotate() {
+                eprintln!("Failed to rotate {}: {}", self.path.display(), e);
+            }
+        }
+
+        if self.file.write_all(line.as_bytes()).is_ok() {
+            self.size += line.len() as u64;
+        }
+    }
+
+    /// Shifts `grout.log` to `grout.log.1`, `grout.log.1` to `grout.log.2` and so on,
+    /// dropping the oldest, then starts a new `grout.log`
+    fn rotate(&mut self) -> Result<()> {
+        for index in (1..ROTATED_LOGS).rev() {
+            let from = rotated_path(&self.path, index);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, index + 1))?;
+            }
+        }
+
+        fs::rename(&self.path, rotated_path(&self.path, 1))?;
+
+        *self = LogFile::open(self.path.clone())?;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".{}", index));
+    path.with_file_name(file_name)
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut line = format!(
+            "{} {:<5} {}: {}",
+            timestamp(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+
+        // Key values such as `operation = "SetWindowPos"` follow the message
+        let _ = record.key_values().visit(&mut KeyValues(&mut line));
+
+        eprintln!("{}", line);
+
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            line.push('\n');
+            file.write(&line);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// Appends each key value as ` key=value`, quoting values with spaces
+struct KeyValues<'a>(&'a mut String);
+
+impl<'kvs> VisitSource<'kvs> for KeyValues<'_> {
+    fn visit_pair(
+        &mut self,
+        key: kv::Key<'kvs>,
+        value: kv::Value<'kvs>,
+    ) -> result::Result<(), kv::Error> {
+        let value = value.to_string();
+
+        if value.is_empty() || value.contains(char::is_whitespace) {
+            let _ = write!(self.0, " {}={:?}", key, value);
+        } else {
+            let _ = write!(self.0, " {}={}", key, value);
+        }
+
+        Ok(())
+    }
+}
+
+/// Current time in UTC, such as `2024-05-01T09:30:00.123Z`
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let seconds = now.as_secs() as i64;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+    let time = seconds.rem_euclid(86400);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        now.subsec_millis()
+    )
+}
+
+/// Year, month and day of the date `days` after 1970-01-01, from
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+pub fn init() {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
+}
+
+/// `grout.log` in the config directory's `logs` directory
+pub fn log_path() -> Result<PathBuf> {
+    Ok(config::config_dir()?.join(LOG_DIR).join(LOG_FILE))
+}
+
+/// Starts writing the log to `log_path`, as well as to stderr
+pub fn log_to_file() {
+    match log_path().and_then(LogFile::open) {
+        Ok(file) => *LOGGER.file.lock().unwrap() = Some(file),
+        Err(e) => log::warn!("Failed to open log file: {}", e),
+    }
+}
+
+pub fn set_level(level: LevelFilter) {
+    log::set_max_level(level);
+}
+
+/// Records a failed Win32 call on `hwnd`, with the window's exe and title so
+/// it's clear which window was involved
+pub fn log_win32_error(operation: &str, hwnd: HWND, error: &dyn fmt::Display) {
+    let window = format!("{:#x}", hwnd.0);
+    let (exe, title) = unsafe { (window_exe(hwnd), window_title(hwnd)) };
+
+    log::warn!(
+        operation = operation,
+        window = window.as_str(),
+        exe = exe.as_str(),
+        title = title.as_str();
+        "{} failed: {}",
+        operation,
+        error
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(20379), (2025, 10, 18));
+    }
+}
@@ -3,34 +3,54 @@
 
 use anyhow::Error;
 use crossbeam_channel::{bounded, select, unbounded, Receiver, Sender};
-use csscolorparser::Color;
 use lazy_static::lazy_static;
 use std::{
-    mem, result,
+    env, mem, process, result,
     sync::{Arc, Mutex},
+    thread,
 };
 use windows::Win32::UI::{
     Input::KeyboardAndMouse::{TrackMouseEvent, TME_LEAVE, TRACKMOUSEEVENT},
     WindowsAndMessaging::{SetForegroundWindow, ShowWindow, SW_SHOW},
 };
 
+use crate::action::Action;
+use crate::bus::Event;
 use crate::common::{
-    focus_window, get_foreground_window, nav_window_list, next_window, order_window_list,
-    previous_window, report_and_exit, show_msg_box, OrderingDirection, Rect,
+    ask_yes_no, enumerate_monitors, get_active_monitor, get_foreground_window, show_msg_box,
+    Monitor, Rect,
 };
-use crate::event::{spawn_foreground_hook, spawn_track_monitor_thread};
-use crate::grid::Grid;
-use crate::hotkey::{spawn_hotkey_thread, HotkeyType};
-use crate::tray::spawn_sys_tray;
-use crate::window::{spawn_grid_window, spawn_preview_window, Window};
+use crate::event::{
+    spawn_config_watcher, spawn_foreground_hook, spawn_track_monitor_thread, spawn_window_hook,
+};
+use crate::grid::{upgrade_grid_cache, Grid};
+use crate::hotkey::{
+    hotkey_bindings, leader_sequences, spawn_hotkey_thread, HotkeyError, HotkeyThread,
+};
+use crate::tray::{notify_hotkey_errors, spawn_sys_tray, update_tray_tooltip};
+use crate::window::{spawn_grid_window, spawn_leader_window, spawn_preview_window, Window};
 
+mod action;
+mod arrangement;
 mod autostart;
+mod bus;
+mod cache;
+mod cli;
 mod common;
 mod config;
 mod event;
 mod grid;
+mod hook;
 mod hotkey;
+mod instance;
+mod ipc;
+mod logging;
+mod migrate;
+mod profile;
+mod script;
+mod settings;
 mod tray;
+mod validate;
 mod window;
 
 lazy_static! {
@@ -38,22 +58,37 @@ lazy_static! {
     static ref CONFIG: Arc<Mutex<config::Config>> = {
         match config::load_config() {
             Ok(config) => Arc::new(Mutex::new(config)),
-            Err(e) => report_and_exit(&format!("Could not load config. Check config file for formatting errors and relaunch program.\n\nErr: {}", e)),
+            Err(e) => {
+                log::error!("Could not load config: {}", e);
+
+                let start_with_defaults = ask_yes_no(&format!("Could not load config. Fix the following problems, changes are applied when the config file is saved.\n\n{}\n\nStart with the default config until then?", e));
+                if !start_with_defaults {
+                    process::exit(1);
+                }
+
+                Arc::new(Mutex::new(config::Config::default()))
+            }
         }
     };
     static ref GRID: Arc<Mutex<Grid>> = Arc::new(Mutex::new(Grid::from(&*CONFIG.lock().unwrap())));
-    static ref ACTIVE_PROFILE: Arc<Mutex<String>> = Arc::new(Mutex::new("Default".to_owned()));
+    static ref ACTIVE_PROFILE: Arc<Mutex<String>> = Arc::new(Mutex::new(profile::startup_profile(
+        &CONFIG.lock().unwrap()
+    )));
+    static ref APPLICATION_PROFILE: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+    static ref HOTKEY_ERRORS: Arc<Mutex<Vec<HotkeyError>>> = Arc::new(Mutex::new(vec![]));
 }
 
 pub enum Message {
     PreviewWindow(Window),
     GridWindow(Window),
     HighlightZone(Rect),
-    HotkeyPressed(HotkeyType),
+    Action(Action),
     TrackMouse(Window),
     ActiveWindowChange(Window),
-    ProfileChange(&'static str),
+    ProfileChange(String),
     MonitorChange,
+    DisplayChange,
+    ConfigChange,
     MouseLeft,
     InitializeWindows,
     CloseWindows,
@@ -72,12 +107,46 @@ macro_rules! str_to_wide {
 pub type Result<T> = result::Result<T, Error>;
 
 fn main() {
+    logging::init();
+
+    let mut args = cli::take_global_options(env::args().skip(1).collect());
+    let replace = cli::take_replace_flag(&mut args);
+
+    if let Some(exit_code) = cli::run(&args) {
+        process::exit(exit_code);
+    }
+
+    let _instance_lock = match instance::lock_instance(replace) {
+        Ok(Some(lock)) => lock,
+        Ok(None) => {
+            log::info!("grout is already running, exiting");
+            return;
+        }
+        Err(e) => {
+            show_msg_box(&format!(
+                "Could not replace the running instance.\n\nErr: {}",
+                e
+            ));
+            process::exit(1);
+        }
+    };
+
+    // Only the running instance writes the log file, commands log to the console
+    logging::log_to_file();
+    log::info!("Starting grout {}", env!("CARGO_PKG_VERSION"));
+
     let receiver = &CHANNEL.1.clone();
     let sender = &CHANNEL.0.clone();
 
     let close_channel = bounded::<()>(3);
 
     let config = CONFIG.lock().unwrap().clone();
+    logging::set_level(config.log_level());
+
+    // Resolving the startup profile locks CONFIG, so do it before anything holds that lock
+    lazy_static::initialize(&ACTIVE_PROFILE);
+
+    upgrade_grid_cache(&config);
 
     unsafe {
         if let Err(e) = autostart::toggle_autostart_registry_key(config.auto_start) {
@@ -88,59 +157,31 @@ fn main() {
         };
     }
 
-    spawn_hotkey_thread(&config.hotkey, HotkeyType::Main);
-
-    if let Some(optional_hotkeys) = &config.optional_hotkeys {
-        if let Some(hotkey) = &optional_hotkeys.quick_resize {
-            spawn_hotkey_thread(hotkey, HotkeyType::QuickResize);
-        }
-
-        if let Some(hotkey_maximize) = &optional_hotkeys.maximize_toggle {
-            spawn_hotkey_thread(hotkey_maximize, HotkeyType::Maximize);
-        }
-
-        if let Some(hotkey) = &optional_hotkeys.quick_exit {
-            spawn_hotkey_thread(hotkey, HotkeyType::Exit);
-        }
-
-        if let Some(navigate_hotkeys) = &optional_hotkeys.navigate {
-            if let Some(hotkey) = &navigate_hotkeys.left {
-                spawn_hotkey_thread(hotkey, HotkeyType::NavigateLeft);
-            }
-            if let Some(hotkey) = &navigate_hotkeys.down {
-                spawn_hotkey_thread(hotkey, HotkeyType::NavigateDown);
-            }
-            if let Some(hotkey) = &navigate_hotkeys.up {
-                spawn_hotkey_thread(hotkey, HotkeyType::NavigateUp);
-            }
-            if let Some(hotkey) = &navigate_hotkeys.right {
-                spawn_hotkey_thread(hotkey, HotkeyType::NavigateRight);
-            }
-        }
-    }
+    let hotkeys = spawn_hotkey_thread();
+    register_hotkeys(&hotkeys, &config);
 
     unsafe {
         spawn_sys_tray();
     }
 
-    let mut preview_color: Color = [0, 77, 128, 107].into();
-    if let Some(colors) = &config.colors {
-        if let Some(color) = &colors.preview {
-            preview_color = color.clone();
-        }
-    }
-
-    let mut grid_background: Color = [44, 44, 44, 255].into();
-    if let Some(colors) = &config.colors {
-        if let Some(color) = &colors.grid_background {
-            grid_background = color.clone();
-        }
-    }
+    spawn_config_watcher();
+    spawn_window_hook();
+    ipc::spawn_ipc_server(ipc::NamedPipe::new());
+    script::spawn_script_thread();
+    hook::spawn_hook_thread();
 
     let mut preview_window: Option<Window> = None;
     let mut grid_window: Option<Window> = None;
     let mut track_mouse = false;
 
+    // A fixed startup profile takes precedence over the monitor setup at launch
+    let mut monitor_setup = if config.startup_profile.is_some() {
+        enumerate_monitors()
+    } else {
+        vec![]
+    };
+    check_monitor_setup(&mut monitor_setup);
+
     loop {
         select! {
             recv(receiver) -> msg => {
@@ -161,8 +202,12 @@ fn main() {
                         grid.grid_window = Some(window);
                         grid.active_window = Some(get_foreground_window());
 
+                        bus::publish(Event::grid_opened());
+
+                        let preview_color = active_profile_config().preview_color();
+
                         spawn_track_monitor_thread(close_channel.1.clone());
-                        spawn_preview_window(close_channel.1.clone(), preview_color.clone());
+                        spawn_preview_window(close_channel.1.clone(), preview_color);
                     }
                     Message::HighlightZone(rect) => {
                         let mut preview_window = preview_window.unwrap_or_default();
@@ -170,80 +215,44 @@ fn main() {
 
                         preview_window.set_pos(rect, Some(grid_window));
                     }
-                    Message::HotkeyPressed(hotkey_type) => {
-                        if hotkey_type == HotkeyType::Maximize {
-                            let mut grid = GRID.lock().unwrap();
-
-                            let mut active_window = if grid_window.is_some() {
-                                grid.active_window.unwrap()
-                            } else {
-                                let active_window = get_foreground_window();
-                                grid.active_window = Some(active_window);
-                                active_window
-                            };
-
-                            let active_rect = active_window.rect();
-
-                            active_window.restore();
-
-                            let mut max_rect = grid.get_max_area();
-                            max_rect.adjust_for_border(active_window.transparent_border());
-
-                            if let Some((_, previous_rect)) = grid.previous_resize {
-                                if active_rect == max_rect {
-                                    active_window.set_pos(previous_rect, None);
-                                } else {
-                                    active_window.set_pos(max_rect, None);
-                                }
-                            } else {
-                                active_window.set_pos(max_rect, None);
-                            }
-
-                            grid.previous_resize = Some((active_window, active_rect));
-
-                        } else if preview_window.is_some() && grid_window.is_some() {
+                    Message::Action(action) => match action {
+                        Action::Maximize => toggle_maximize(grid_window.is_some()),
+                        Action::SwitchProfile { profile } => {
+                            let _ = sender.send(Message::ProfileChange(profile));
+                        }
+                        _ if preview_window.is_some() && grid_window.is_some() => {
                             let _ = sender.send(Message::CloseWindows);
-                        } else if hotkey_type == HotkeyType::NavigateLeft {
-                            let mut windows = nav_window_list().expect("failed nav_window_list");
-                            if !windows.is_empty() {
-                                order_window_list(&mut windows, OrderingDirection::Horizontal);
-                                if let Some(hwnd) = previous_window(&windows) {
-                                    focus_window(*hwnd);
-                                }
-                            }
-                        } else if hotkey_type == HotkeyType::NavigateDown {
-                            let mut windows = nav_window_list().expect("failed nav_window_list");
-                            if !windows.is_empty() {
-                                order_window_list(&mut windows, OrderingDirection::Vertical);
-                                if let Some(hwnd) = next_window(&windows) {
-                                    focus_window(*hwnd);
-                                }
-                            }
-                        } else if hotkey_type == HotkeyType::NavigateUp {
-                            let mut windows = nav_window_list().expect("failed nav_window_list");
-                            if !windows.is_empty() {
-                                order_window_list(&mut windows, OrderingDirection::Vertical);
-                                if let Some(hwnd) = previous_window(&windows) {
-                                    focus_window(*hwnd);
-                                }
-                            }
-                        } else if hotkey_type == HotkeyType::NavigateRight {
-                            let mut windows = nav_window_list().expect("failed nav_window_list");
-                            if !windows.is_empty() {
-                                order_window_list(&mut windows, OrderingDirection::Horizontal);
-                                if let Some(hwnd) = next_window(&windows) {
-                                    focus_window(*hwnd);
-                                }
-                            }
-                        } else if hotkey_type == HotkeyType::Exit {
-                            std::process::exit(0);
-                        } else {
-                            let _ = sender.send(Message::InitializeWindows);
-                            if hotkey_type == HotkeyType::QuickResize {
-                                GRID.lock().unwrap().quick_resize = true;
+                        }
+                        Action::Focus { direction } => action::focus(direction),
+                        Action::MoveToZone {
+                            row,
+                            column,
+                            row_span,
+                            column_span,
+                            monitor,
+                        } => {
+                            action::move_to_zone(
+                                row,
+                                column,
+                                row_span.unwrap_or(1),
+                                column_span.unwrap_or(1),
+                                monitor,
+                            );
+                        }
+                        Action::SaveSnapshot { name } => {
+                            arrangement::save_snapshot(&name);
+                        }
+                        Action::RestoreSnapshot { name } => {
+                            if !arrangement::restore_snapshot(&name) {
+                                log::warn!("No snapshot named {}", name);
                             }
                         }
-                    }
+                        Action::Script { function } => script::call(function),
+                        Action::Exit => std::process::exit(0),
+                        Action::Leader => open_leader(),
+                        Action::OpenGrid { profile } => open_grid(profile, false),
+                        Action::QuickResize { profile } => open_grid(profile, true),
+                    },
                     Message::TrackMouse(window) => unsafe {
                         if !track_mouse {
                             let mut event_track: TRACKMOUSEEVENT = mem::zeroed();
@@ -260,50 +269,83 @@ fn main() {
                         track_mouse = false;
                     }
                     Message::ActiveWindowChange(window) => {
-                        let mut grid = GRID.lock().unwrap();
+                        let changed = {
+                            let mut grid = GRID.lock().unwrap();
 
-                        if grid.grid_window != Some(window) && grid.active_window != Some(window) {
-                            grid.active_window = Some(window);
+                            if grid.grid_window != Some(window)
+                                && grid.active_window != Some(window)
+                            {
+                                grid.active_window = Some(window);
+                                true
+                            } else {
+                                false
+                            }
+                        };
+
+                        if changed
+                            && profile::update_application_profile(window)
+                            && grid_window.is_some()
+                        {
+                            rebuild_grid(grid_window);
                         }
                     }
                     Message::MonitorChange => {
-                        let mut grid = GRID.lock().unwrap();
+                        bus::publish(Event::MonitorChanged {
+                            monitor: unsafe { get_active_monitor() },
+                        });
+
+                        // A profile matching the new monitor setup wins over the one
+                        // last used on the active monitor
+                        if !check_monitor_setup(&mut monitor_setup) {
+                            restore_monitor_profile();
+                        }
 
-                        let active_window = grid.active_window;
-                        let previous_resize = grid.previous_resize;
-                        let quick_resize = grid.quick_resize;
+                        rebuild_grid(grid_window);
+                    }
+                    Message::DisplayChange => {
+                        check_monitor_setup(&mut monitor_setup);
+                    }
+                    Message::ConfigChange => {
+                        let reloaded = reload_config(&hotkeys);
 
-                        *grid = Grid::from(&*CONFIG.lock().unwrap());
+                        // The grid is rebuilt from the new config when it's next opened
+                        if let (true, Some(window)) = (reloaded, grid_window) {
+                            rebuild_grid(grid_window);
 
-                        grid.grid_window = grid_window;
-                        grid.active_window = active_window;
-                        grid.previous_resize = previous_resize;
-                        grid.quick_resize = quick_resize;
-
-                        grid.reposition();
+                            let config = active_profile_config();
+                            window.set_background(&config.grid_background());
+                            if let Some(window) = preview_window {
+                                window.set_background(&config.preview_color());
+                            }
+                        }
                     }
                     Message::ProfileChange(profile) => {
+                        profile::remember_profile(&CONFIG.lock().unwrap(), &profile);
+
                         {
                             let mut active_profile = ACTIVE_PROFILE.lock().unwrap();
-                            *active_profile = profile.to_owned();
+                            *active_profile = profile;
                         }
 
-                        let mut grid = GRID.lock().unwrap();
+                        // An explicit switch wins over the foreground application's profile
+                        APPLICATION_PROFILE.lock().unwrap().take();
 
-                        let active_window = grid.active_window;
-                        let previous_resize = grid.previous_resize;
-                        let quick_resize = grid.quick_resize;
+                        update_tray_tooltip();
 
-                        *grid = Grid::from(&*CONFIG.lock().unwrap());
+                        bus::publish(Event::ProfileChanged {
+                            profile: profile::current_profile(),
+                        });
 
-                        grid.grid_window = grid_window;
-                        grid.active_window = active_window;
-                        grid.previous_resize = previous_resize;
-                        grid.quick_resize = quick_resize;
+                        if grid_window.is_none() {
+                            continue;
+                        }
 
-                        grid.reposition();
+                        rebuild_grid(grid_window);
                     }
                     Message::InitializeWindows => {
+                        restore_monitor_profile();
+                        profile::update_application_profile(get_foreground_window());
+
                         let mut grid = GRID.lock().unwrap();
                         let quick_resize = grid.quick_resize;
                         let previous_resize = grid.previous_resize;
@@ -312,12 +354,17 @@ fn main() {
 
                         grid.quick_resize = quick_resize;
                         grid.previous_resize = previous_resize;
+                        grid.touch();
+
+                        let grid_background = active_profile_config().grid_background();
 
-                        spawn_grid_window(close_channel.1.clone(), grid_background.clone());
+                        spawn_grid_window(close_channel.1.clone(), grid_background);
                     }
                     Message::CloseWindows => {
                         preview_window.take();
-                        grid_window.take();
+                        if grid_window.take().is_some() {
+                            bus::publish(Event::GridClosed);
+                        }
 
                         for _ in 0..4 {
                             let _ = close_channel.0.send(());
@@ -336,3 +383,174 @@ fn main() {
         }
     }
 }
+
+/// Maximizes the active window, or restores it to its size before the last resize
+fn toggle_maximize(grid_open: bool) {
+    let mut grid = GRID.lock().unwrap();
+
+    let mut active_window = if grid_open {
+        grid.active_window.unwrap()
+    } else {
+        let active_window = get_foreground_window();
+        grid.active_window = Some(active_window);
+        active_window
+    };
+
+    let active_rect = active_window.rect();
+
+    active_window.restore();
+
+    let mut max_rect = grid.get_max_area();
+    max_rect.adjust_for_border(active_window.transparent_border());
+
+    if let Some((_, previous_rect)) = grid.previous_resize {
+        if active_rect == max_rect {
+            active_window.set_pos(previous_rect, None);
+        } else {
+            active_window.set_pos(max_rect, None);
+        }
+    } else {
+        active_window.set_pos(max_rect, None);
+    }
+
+    let profile = profile::current_profile();
+    arrangement::record_placement(&profile, active_window, active_window.rect());
+    bus::publish(Event::window_placed(active_window, None));
+
+    grid.previous_resize = Some((active_window, active_rect));
+}
+
+/// Opens the grid, switching to `profile` first if given
+fn open_grid(profile: Option<String>, quick_resize: bool) {
+    if let Some(profile) = profile {
+        let _ = CHANNEL.0.send(Message::ProfileChange(profile));
+    }
+
+    let _ = CHANNEL.0.send(Message::InitializeWindows);
+    if quick_resize {
+        GRID.lock().unwrap().quick_resize = true;
+    }
+}
+
+/// Registers the hotkeys and leader sequences of `config`, replacing any registered before
+fn register_hotkeys(hotkeys: &HotkeyThread, config: &config::Config) {
+    let mut errors = hotkeys.register(hotkey_bindings(config));
+    errors.extend(leader_sequences(config).1);
+
+    *HOTKEY_ERRORS.lock().unwrap() = errors;
+    update_tray_tooltip();
+}
+
+/// Reloads the config file, keeping the current config if the new one fails to
+/// load. Returns true if the new config was applied.
+fn reload_config(hotkeys: &HotkeyThread) -> bool {
+    let config = match config::load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            log::warn!("Failed to reload config: {}", e);
+
+            let msg = format!("Could not reload config. Check config file for formatting errors, the previous config is still in use.\n\nErr: {}", e);
+            thread::spawn(move || show_msg_box(&msg));
+
+            return false;
+        }
+    };
+
+    let auto_start_changed = CONFIG.lock().unwrap().auto_start != config.auto_start;
+    *CONFIG.lock().unwrap() = config.clone();
+
+    logging::set_level(config.log_level());
+    upgrade_grid_cache(&config);
+
+    if auto_start_changed {
+        if let Err(e) = unsafe { autostart::toggle_autostart_registry_key(config.auto_start) } {
+            log::warn!("Failed to update autostart registry key: {}", e);
+        }
+    }
+
+    register_hotkeys(hotkeys, &config);
+    notify_hotkey_errors();
+
+    log::info!("Reloaded config");
+    true
+}
+
+/// Waits for one of the leader sequences, showing a hint of the available ones
+fn open_leader() {
+    let config = active_profile_config();
+    let (sequences, _) = leader_sequences(&config);
+
+    spawn_leader_window(
+        sequences,
+        config.leader_timeout(),
+        config.grid_background(),
+        config.tile_color(),
+    );
+}
+
+/// Switches to the profile matching the connected monitors, if the setup changed.
+/// Returns true if it switched profile.
+fn check_monitor_setup(monitor_setup: &mut Vec<Monitor>) -> bool {
+    let monitors = enumerate_monitors();
+    if *monitor_setup == monitors {
+        return false;
+    }
+    *monitor_setup = monitors;
+
+    bus::publish(Event::DisplayChanged {
+        monitors: monitor_setup.clone(),
+    });
+
+    let profile = profile::monitor_setup_profile(&CONFIG.lock().unwrap(), monitor_setup);
+
+    let mut switched = false;
+
+    if let Some(profile) = profile {
+        if *ACTIVE_PROFILE.lock().unwrap() != profile.name {
+            let _ = CHANNEL.0.send(Message::ProfileChange(profile.name.clone()));
+            switched = true;
+        }
+
+        if profile.restore_arrangement {
+            arrangement::restore_arrangement(&profile.name);
+        }
+    }
+
+    switched
+}
+
+/// Switches to the profile last used on the active monitor, if tracked per monitor
+fn restore_monitor_profile() {
+    let profile = profile::monitor_profile(&CONFIG.lock().unwrap());
+
+    if let Some(profile) = profile {
+        if *ACTIVE_PROFILE.lock().unwrap() != profile {
+            let _ = CHANNEL.0.send(Message::ProfileChange(profile));
+        }
+    }
+}
+
+/// Recreates the grid for the current profile and monitor, keeping window state
+fn rebuild_grid(grid_window: Option<Window>) {
+    let mut grid = GRID.lock().unwrap();
+
+    let active_window = grid.active_window;
+    let previous_resize = grid.previous_resize;
+    let quick_resize = grid.quick_resize;
+
+    *grid = Grid::from(&*CONFIG.lock().unwrap());
+
+    grid.grid_window = grid_window;
+    grid.active_window = active_window;
+    grid.previous_resize = previous_resize;
+    grid.quick_resize = quick_resize;
+
+    grid.reposition();
+}
+
+/// Config with the overrides of the current profile applied
+fn active_profile_config() -> config::Config {
+    let profile = profile::current_profile();
+
+    CONFIG.lock().unwrap().with_profile(&profile)
+}
@@ -0,0 +1,235 @@
+use std::path::Path;
+use std::thread;
+use toml_edit::{DocumentMut, Item, Key, Table};
+
+use crate::cache::write_atomic;
+use crate::common::show_msg_box;
+
+/// Version of the config format used by this release
+pub const CONFIG_VERSION: i64 = 1;
+
+struct Migration {
+    /// Version the config is at after this migration
+    version: i64,
+    apply: fn(&mut DocumentMut) -> Vec<String>,
+}
+
+const MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    apply: move_optional_hotkeys,
+}];
+
+/// Rewrites keys renamed since the config's version, keeping comments and
+/// formatting. `version` is only set when a key was rewritten, so files with
+/// nothing to migrate, such as includes, are left as they are. Returns a
+/// description of each change, empty if nothing changed.
+pub fn migrate_config(document: &mut DocumentMut) -> Vec<String> {
+    let version = document
+        .get("version")
+        .and_then(Item::as_integer)
+        .unwrap_or(0);
+
+    if version >= CONFIG_VERSION {
+        return vec![];
+    }
+
+    let mut changes = MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > version)
+        .flat_map(|migration| (migration.apply)(document))
+        .collect::<Vec<_>>();
+
+    if !changes.is_empty() {
+        document["version"] = toml_edit::value(CONFIG_VERSION);
+        changes.push(format!("Set `version` to {}", CONFIG_VERSION));
+    }
+
+    changes
+}
+
+/// Migrated contents of a config file and the changes made, or None if there was
+/// nothing to migrate
+fn migrate_contents(contents: &str) -> Option<(String, Vec<String>)> {
+    // Syntax errors are reported by the validator
+    let mut document = contents.parse::<DocumentMut>().ok()?;
+
+    let changes = migrate_config(&mut document);
+    if changes.is_empty() {
+        return None;
+    }
+
+    Some((document.to_string(), changes))
+}
+
+/// The contents of a config file as they'd be after migrating, without writing
+/// them anywhere
+pub fn migrated_config(contents: String) -> String {
+    match migrate_contents(&contents) {
+        Some((migrated, _)) => migrated,
+        None => contents,
+    }
+}
+
+/// Migrates the config file at `path` in place, returning the migrated contents.
+/// The user is told about any keys that were moved.
+pub fn migrate_config_file(path: &Path, contents: String) -> String {
+    let (migrated, changes) = match migrate_contents(&contents) {
+        Some(migrated) => migrated,
+        None => return contents,
+    };
+
+    if let Err(e) = write_atomic(path, &migrated) {
+        log::warn!(
+            "Failed to save migrated config to {}: {}",
+            path.display(),
+            e
+        );
+    }
+
+    for change in &changes {
+        log::info!("Config migration: {}", change);
+    }
+
+    let msg = format!(
+        "Updated {} to the current config format. The previous file was saved with a .bak extension.\n\n- {}",
+        path.display(),
+        changes.join("\n- ")
+    );
+    thread::spawn(move || show_msg_box(&msg));
+
+    migrated
+}
+
+/// Version 1 moved the top level `hotkey_*` keys into `[optional_hotkeys]`
+fn move_optional_hotkeys(document: &mut DocumentMut) -> Vec<String> {
+    let renames = [
+        ("hotkey_quick_resize", "quick_resize"),
+        ("hotkey_maximize_toggle", "maximize_toggle"),
+        ("hotkey_quick_exit", "quick_exit"),
+    ];
+
+    let mut changes = vec![];
+
+    for (old_name, new_name) in renames.iter() {
+        let (old_key, item) = match document.remove_entry(old_name) {
+            Some(entry) => entry,
+            None => continue,
+        };
+
+        if !document.contains_key("optional_hotkeys") {
+            let mut table = Table::new();
+            table.decor_mut().set_prefix("\n");
+            document.insert("optional_hotkeys", Item::Table(table));
+        }
+
+        let optional_hotkeys = &mut document["optional_hotkeys"];
+
+        if optional_hotkeys.get(new_name).is_some() {
+            changes.push(format!(
+                "Removed `{}`, `optional_hotkeys.{}` is already set",
+                old_name, new_name
+            ));
+            continue;
+        }
+
+        let new_key = Key::new(*new_name).with_leaf_decor(old_key.leaf_decor().clone());
+
+        match optional_hotkeys {
+            Item::Table(table) => {
+                table.insert_formatted(&new_key, item);
+            }
+            optional_hotkeys => match optional_hotkeys.as_table_like_mut() {
+                Some(table) => {
+                    table.insert(new_name, item);
+                }
+                None => {
+                    // Leave keys in place rather than losing them when the table is malformed
+                    document.insert_formatted(&old_key, item);
+                    continue;
+                }
+            },
+        }
+
+        changes.push(format!(
+            "Moved `{}` to `optional_hotkeys.{}`",
+            old_name, new_name
+        ));
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrate(contents: &str) -> (String, Vec<String>) {
+        let mut document = contents.parse::<DocumentMut>().unwrap();
+        let changes = migrate_config(&mut document);
+        (document.to_string(), changes)
+    }
+
+    #[test]
+    fn moves_hotkeys_keeping_comments() {
+        let (migrated, changes) = migrate(
+            "margins = 10 # around the grid\n\n# Resize without the grid\nhotkey_quick_resize = \"CTRL+ALT+Q\"\n",
+        );
+
+        assert_eq!(
+            changes,
+            [
+                "Moved `hotkey_quick_resize` to `optional_hotkeys.quick_resize`",
+                "Set `version` to 1"
+            ]
+        );
+        assert!(
+            migrated.contains("margins = 10 # around the grid"),
+            "{}",
+            migrated
+        );
+        assert!(
+            migrated.contains(
+                "[optional_hotkeys]\n\n# Resize without the grid\nquick_resize = \"CTRL+ALT+Q\""
+            ),
+            "{}",
+            migrated
+        );
+        assert!(!migrated.contains("hotkey_quick_resize"), "{}", migrated);
+    }
+
+    #[test]
+    fn keeps_hotkey_already_set() {
+        let (migrated, changes) = migrate(
+            "hotkey_quick_exit = \"CTRL+ALT+X\"\n\n[optional_hotkeys]\nquick_exit = \"CTRL+ALT+Q\"\n",
+        );
+
+        assert_eq!(
+            changes[0],
+            "Removed `hotkey_quick_exit`, `optional_hotkeys.quick_exit` is already set"
+        );
+        assert!(
+            migrated.contains("quick_exit = \"CTRL+ALT+Q\""),
+            "{}",
+            migrated
+        );
+        assert!(!migrated.contains("CTRL+ALT+X"), "{}", migrated);
+    }
+
+    #[test]
+    fn leaves_hotkeys_when_optional_hotkeys_is_malformed() {
+        let contents = "optional_hotkeys = \"CTRL+ALT+Q\"\nhotkey_quick_resize = \"CTRL+ALT+R\"\n";
+        let (migrated, changes) = migrate(contents);
+
+        assert!(changes.is_empty(), "{:?}", changes);
+        assert_eq!(migrated, contents);
+    }
+
+    #[test]
+    fn leaves_files_without_old_keys_unversioned() {
+        let contents = "# Shared colors\n[colors]\ntile = \"#004d80\"\n";
+        let (migrated, changes) = migrate(contents);
+
+        assert!(changes.is_empty(), "{:?}", changes);
+        assert_eq!(migrated, contents);
+    }
+}
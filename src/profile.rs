@@ -0,0 +1,237 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::cache::{cache_file, write_atomic};
+use crate::common::{get_active_monitor, window_exe, Monitor};
+use crate::config::{Config, MonitorMatch, Profile};
+use crate::window::Window;
+use crate::{ACTIVE_PROFILE, APPLICATION_PROFILE, CONFIG};
+
+/// Last active profile, globally and for each monitor, persisted across restarts
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct ProfileState {
+    active: Option<String>,
+    monitors: HashMap<String, String>,
+}
+
+impl ProfileState {
+    pub fn load() -> ProfileState {
+        if let Ok(path) = cache_file("profile.ron") {
+            if let Ok(contents) = fs::read_to_string(&path) {
+                match ron::de::from_str(&contents) {
+                    Ok(state) => return state,
+                    Err(e) => log::warn!("Failed to load {}: {}", path.display(), e),
+                }
+            }
+        }
+
+        ProfileState::default()
+    }
+
+    pub fn save(&self) {
+        let result = cache_file("profile.ron").and_then(|path| {
+            let serialized = ron::ser::to_string(&self)?;
+            write_atomic(&path, &serialized)
+        });
+
+        if let Err(e) = result {
+            log::warn!("Failed to save active profile: {}", e);
+        }
+    }
+}
+
+/// Profile to activate when the program starts
+pub fn startup_profile(config: &Config) -> String {
+    if let Some(profile) = &config.startup_profile {
+        if config.profile(profile).is_some() {
+            return profile.clone();
+        }
+    }
+
+    ProfileState::load()
+        .active
+        .filter(|profile| config.profile(profile).is_some())
+        .unwrap_or_else(|| config.default_profile())
+}
+
+/// Profile last used on the monitor under the cursor, if profiles are tracked per monitor
+pub fn monitor_profile(config: &Config) -> Option<String> {
+    if !config.profile_per_monitor || config.startup_profile.is_some() {
+        return None;
+    }
+
+    let monitor = config.monitor_key(&unsafe { get_active_monitor() });
+
+    ProfileState::load()
+        .monitors
+        .remove(&monitor)
+        .filter(|profile| config.profile(profile).is_some())
+}
+
+/// Persists `profile` as the last active profile, globally and for the active monitor
+pub fn remember_profile(config: &Config, profile: &str) {
+    let monitor = config.monitor_key(&unsafe { get_active_monitor() });
+
+    let mut state = ProfileState::load();
+    state.active = Some(profile.to_owned());
+    state.monitors.insert(monitor, profile.to_owned());
+    state.save();
+}
+
+/// First profile whose monitor fingerprint matches the connected monitors
+pub fn monitor_setup_profile(config: &Config, monitors: &[Monitor]) -> Option<Profile> {
+    config.profiles().into_iter().find(|profile| {
+        profile
+            .monitors
+            .as_ref()
+            .is_some_and(|fingerprint| setup_matches(config, fingerprint, monitors))
+    })
+}
+
+/// Every monitor must be matched by exactly one entry of the fingerprint
+fn setup_matches(config: &Config, fingerprint: &[MonitorMatch], monitors: &[Monitor]) -> bool {
+    if fingerprint.len() != monitors.len() {
+        return false;
+    }
+
+    let candidates = fingerprint
+        .iter()
+        .map(|monitor_match| {
+            monitors
+                .iter()
+                .map(|monitor| monitor_matches(config, monitor_match, monitor))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    matches_every_entry(&candidates, monitors.len())
+}
+
+/// Whether each entry can be given its own monitor, where `candidates[entry][monitor]`
+/// says if the entry matches the monitor. Entries can match several monitors, so a
+/// first fit could take the only monitor a later, more specific entry matches.
+fn matches_every_entry(candidates: &[Vec<bool>], monitors: usize) -> bool {
+    // Entry given each monitor so far
+    let mut assigned = vec![None; monitors];
+
+    (0..candidates.len())
+        .all(|entry| assign_monitor(entry, candidates, &mut assigned, &mut vec![false; monitors]))
+}
+
+/// Gives `entry` a monitor, moving entries already given one of its candidates to
+/// another of theirs if they can
+fn assign_monitor(
+    entry: usize,
+    candidates: &[Vec<bool>],
+    assigned: &mut [Option<usize>],
+    visited: &mut [bool],
+) -> bool {
+    for monitor in 0..assigned.len() {
+        if !candidates[entry][monitor] || visited[monitor] {
+            continue;
+        }
+        visited[monitor] = true;
+
+        let available = match assigned[monitor] {
+            Some(other) => assign_monitor(other, candidates, assigned, visited),
+            None => true,
+        };
+
+        if available {
+            assigned[monitor] = Some(entry);
+            return true;
+        }
+    }
+
+    false
+}
+
+fn monitor_matches(config: &Config, monitor_match: &MonitorMatch, monitor: &Monitor) -> bool {
+    monitor_match.device.as_ref().is_none_or(|device| {
+        device.eq_ignore_ascii_case(&monitor.name)
+            || device.eq_ignore_ascii_case(&monitor.id)
+            || config
+                .monitor_alias(monitor)
+                .is_some_and(|alias| device.eq_ignore_ascii_case(&alias))
+    }) && monitor_match
+        .width
+        .is_none_or(|width| width == monitor.rect.width)
+        && monitor_match
+            .height
+            .is_none_or(|height| height == monitor.rect.height)
+        && monitor_match.x.is_none_or(|x| x == monitor.rect.x)
+        && monitor_match.y.is_none_or(|y| y == monitor.rect.y)
+}
+
+/// Profile the grid opens in: the foreground application's profile if it has one,
+/// otherwise the active profile
+pub fn current_profile() -> String {
+    if let Some(profile) = APPLICATION_PROFILE.lock().unwrap().clone() {
+        return profile;
+    }
+
+    ACTIVE_PROFILE.lock().unwrap().clone()
+}
+
+/// Updates the application profile for a new foreground window. Returns true if it changed.
+pub fn update_application_profile(window: Window) -> bool {
+    let exe = unsafe { window_exe(window.0) };
+    let profile = application_profile(&CONFIG.lock().unwrap(), &exe);
+
+    let mut application_profile = APPLICATION_PROFILE.lock().unwrap();
+    let changed = *application_profile != profile;
+    *application_profile = profile;
+    changed
+}
+
+/// First profile with an application rule matching `exe`
+fn application_profile(config: &Config, exe: &str) -> Option<String> {
+    if exe.is_empty() {
+        return None;
+    }
+
+    let path = Path::new(exe);
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let file_stem = path
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+
+    config
+        .profiles()
+        .into_iter()
+        .find(|profile| {
+            profile.applications.as_ref().is_some_and(|applications| {
+                applications.iter().any(|application| {
+                    application.eq_ignore_ascii_case(exe)
+                        || application.eq_ignore_ascii_case(file_name)
+                        || application.eq_ignore_ascii_case(file_stem)
+                })
+            })
+        })
+        .map(|profile| profile.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn general_entry_gives_way_to_specific_entry() {
+        // [{ width = 2560 }, { device = "DISPLAY1", width = 2560 }] against two
+        // 2560 wide monitors, DISPLAY1 first
+        let candidates = vec![vec![true, true], vec![true, false]];
+        assert!(matches_every_entry(&candidates, 2));
+    }
+
+    #[test]
+    fn monitor_matched_by_no_entry() {
+        let candidates = vec![vec![true, false], vec![true, false]];
+        assert!(!matches_every_entry(&candidates, 2));
+    }
+}
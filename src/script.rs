@@ -0,0 +1,371 @@
+use crossbeam_channel::{select, tick, unbounded, Sender};
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, INT};
+use serde::Serialize;
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::action::Action;
+use crate::bus::{self, Event};
+use crate::common::{
+    enumerate_monitors, focus_window, get_active_monitor, get_foreground_window, monitor_of_window,
+    nav_window_list, show_msg_box, window_exe, window_title, Rect,
+};
+use crate::config;
+use crate::grid::Grid;
+use crate::profile::current_profile;
+use crate::window::Window;
+use crate::{Message, CHANNEL, CONFIG};
+
+/// Directory in the config directory scripts are loaded from
+const SCRIPT_DIR: &str = "scripts";
+
+/// How long a script can run before it's stopped, so one stuck in a loop can't
+/// hold up other scripts or reloading
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Functions to call, sent by `script` actions
+static CALLS: OnceLock<Sender<String>> = OnceLock::new();
+
+thread_local! {
+    /// When the script running on this thread is stopped
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Loads the `.rhai` scripts in the scripts directory and runs their functions for
+/// `script` actions and events, reloading them when they change
+pub fn spawn_script_thread() {
+    let (sender, calls) = unbounded::<String>();
+    let _ = CALLS.set(sender);
+
+    thread::spawn(move || {
+        let engine = engine();
+        let events = bus::subscribe();
+        let poll = tick(Duration::from_secs(1));
+
+        let mut previous_modified = scripts_modified();
+        let mut scripts = Scripts::load(&engine);
+
+        loop {
+            select! {
+                recv(calls) -> function => {
+                    if let Ok(function) = function {
+                        scripts.call_action(&engine, &function);
+                    }
+                }
+                recv(events) -> event => {
+                    if let Ok(event) = event {
+                        scripts.call_hook(&engine, &event);
+                    }
+                }
+                recv(poll) -> _ => {
+                    let modified = scripts_modified();
+                    if modified != previous_modified {
+                        // Give editors that save in several steps time to finish writing
+                        thread::sleep(Duration::from_millis(250));
+                        previous_modified = scripts_modified();
+
+                        scripts = Scripts::load(&engine);
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Runs the script function `function` on the script thread
+pub fn call(function: String) {
+    match CALLS.get() {
+        Some(calls) => {
+            let _ = calls.send(function);
+        }
+        None => log::warn!("Scripts aren't loaded, can't run {}", function),
+    }
+}
+
+/// Every script, compiled into one
+struct Scripts {
+    ast: AST,
+}
+
+impl Scripts {
+    fn load(engine: &Engine) -> Self {
+        let mut ast = AST::empty();
+        let mut errors = vec![];
+
+        for path in script_files() {
+            match engine.compile_file(path.clone()) {
+                Ok(script) => {
+                    ast.combine(script);
+                }
+                Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+            }
+        }
+
+        // Top level statements run once, when the scripts are loaded
+        if let Err(e) = with_timeout(|| engine.run_ast(&ast)) {
+            errors.push(e.to_string());
+        }
+
+        if !errors.is_empty() {
+            let errors = errors.join("\n");
+            log::error!("Could not load scripts: {}", errors);
+            let msg = format!(
+                "Could not load scripts. Fix the following problems, changes are applied when a script is saved.\n\n{}",
+                errors
+            );
+            thread::spawn(move || show_msg_box(&msg));
+        }
+
+        Scripts { ast }
+    }
+
+    fn has_function(&self, name: &str, params: usize) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|function| function.name == name && function.params.len() == params)
+    }
+
+    fn call_action(&self, engine: &Engine, function: &str) {
+        if !self.has_function(function, 0) {
+            log::warn!("No script function {}()", function);
+            return;
+        }
+
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = with_timeout(|| {
+            engine.call_fn_with_options::<Dynamic>(
+                options,
+                &mut Scope::new(),
+                &self.ast,
+                function,
+                (),
+            )
+        });
+
+        if let Err(e) = result {
+            log::warn!("Script function {} failed: {}", function, e);
+        }
+    }
+
+    /// Calls the hook for `event` if a script defines one, e.g. `on_focus_changed`
+    /// for `focus-changed`, with the event's data as a map
+    fn call_hook(&self, engine: &Engine, event: &Event) {
+        let hook = format!("on_{}", event.name().replace('-', "_"));
+        if !self.has_function(&hook, 1) {
+            return;
+        }
+
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = with_timeout(|| {
+            engine.call_fn_with_options::<Dynamic>(
+                options,
+                &mut Scope::new(),
+                &self.ast,
+                &hook,
+                (to_dynamic(event),),
+            )
+        });
+
+        if let Err(e) = result {
+            log::warn!("Script hook {} failed: {}", hook, e);
+        }
+    }
+}
+
+/// Runs `script`, stopping it if it runs for longer than `SCRIPT_TIMEOUT`
+fn with_timeout<T>(script: impl FnOnce() -> T) -> T {
+    DEADLINE.set(Some(Instant::now() + SCRIPT_TIMEOUT));
+    let result = script();
+    DEADLINE.set(None);
+
+    result
+}
+
+fn script_dir() -> Option<PathBuf> {
+    config::config_dir().ok().map(|dir| dir.join(SCRIPT_DIR))
+}
+
+/// The `.rhai` files in the scripts directory, in the order they're loaded
+fn script_files() -> Vec<PathBuf> {
+    let entries = match script_dir().and_then(|dir| fs::read_dir(dir).ok()) {
+        Some(entries) => entries,
+        None => return vec![],
+    };
+
+    let mut files = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "rhai")
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+/// Every script and when it was last modified, so added and removed scripts
+/// count as changes too
+fn scripts_modified() -> Vec<(PathBuf, Option<SystemTime>)> {
+    script_files()
+        .into_iter()
+        .map(|path| {
+            let modified = fs::metadata(&path)
+                .ok()
+                .and_then(|meta| meta.modified().ok());
+            (path, modified)
+        })
+        .collect()
+}
+
+fn to_dynamic<T: Serialize>(value: &T) -> Dynamic {
+    rhai::serde::to_dynamic(value).unwrap_or(Dynamic::UNIT)
+}
+
+/// Engine with the functions scripts use to inspect and arrange windows
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+
+    engine
+        .on_print(|text| log::info!("[script] {}", text))
+        .on_debug(|text, source, position| {
+            log::debug!("[script] {} {}: {}", source.unwrap_or(""), position, text)
+        })
+        .on_progress(|operations| {
+            // Checking the time is slow compared to an operation
+            if operations % 1024 != 0 {
+                return None;
+            }
+
+            let expired = DEADLINE
+                .get()
+                .is_some_and(|deadline| Instant::now() >= deadline);
+            if expired {
+                let message = format!(
+                    "stopped after running for {} seconds",
+                    SCRIPT_TIMEOUT.as_secs()
+                );
+                return Some(message.into());
+            }
+
+            None
+        });
+
+    engine
+        .register_type_with_name::<Window>("Window")
+        .register_get("exe", |window: &mut Window| unsafe { window_exe(window.0) })
+        .register_get("title", |window: &mut Window| unsafe {
+            window_title(window.0)
+        })
+        .register_get("rect", |window: &mut Window| to_dynamic(&window.rect()))
+        .register_get("monitor", |window: &mut Window| {
+            to_dynamic(&unsafe { monitor_of_window(window.0) })
+        })
+        .register_fn(
+            "set_rect",
+            |window: &mut Window, x: INT, y: INT, width: INT, height: INT| {
+                window.set_pos(rect(x, y, width, height), None);
+            },
+        )
+        .register_fn("move_to", |window: &mut Window, x: INT, y: INT| {
+            let current = window.rect();
+            window.set_pos(
+                rect(x, y, current.width as INT, current.height as INT),
+                None,
+            );
+        })
+        .register_fn("resize", |window: &mut Window, width: INT, height: INT| {
+            let current = window.rect();
+            window.set_pos(
+                rect(current.x as INT, current.y as INT, width, height),
+                None,
+            );
+        })
+        .register_fn("fill", fill)
+        .register_fn("focus", |window: &mut Window| {
+            focus_window(window.0);
+        });
+
+    engine
+        .register_fn("active_window", get_foreground_window)
+        .register_fn("windows", || -> Array {
+            nav_window_list()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|hwnd| Dynamic::from(Window(hwnd)))
+                .collect()
+        })
+        .register_fn("monitors", || -> Array {
+            enumerate_monitors().iter().map(to_dynamic).collect()
+        })
+        .register_fn("active_monitor", || {
+            to_dynamic(&unsafe { get_active_monitor() })
+        })
+        .register_fn("zones", || zones(Grid::from(&*CONFIG.lock().unwrap())))
+        .register_fn("zones", |monitor: INT| -> ScriptResult<Array> {
+            let monitors = enumerate_monitors();
+            let monitor = usize::try_from(monitor)
+                .ok()
+                .and_then(|index| monitors.get(index))
+                .ok_or_else(|| format!("No monitor {}", monitor))?;
+
+            Ok(zones(Grid::for_monitor(&CONFIG.lock().unwrap(), monitor)))
+        })
+        .register_fn("active_profile", current_profile)
+        .register_fn("switch_profile", |profile: &str| {
+            let _ = CHANNEL.0.send(Message::ProfileChange(profile.to_owned()));
+        })
+        .register_fn("action", |action: Map| -> ScriptResult<()> {
+            let action = rhai::serde::from_dynamic::<Action>(&action.into())?;
+            let _ = CHANNEL.0.send(Message::Action(action));
+            Ok(())
+        });
+
+    engine
+}
+
+fn rect(x: INT, y: INT, width: INT, height: INT) -> Rect {
+    Rect {
+        x: x as i32,
+        y: y as i32,
+        width: width as i32,
+        height: height as i32,
+    }
+}
+
+/// Moves `window` to fill `area`, such as a zone's rect, the same way the grid
+/// does
+fn fill(window: &mut Window, area: Map) -> ScriptResult<()> {
+    let mut area = rhai::serde::from_dynamic::<Rect>(&area.into())?;
+
+    window.restore();
+    area.adjust_for_border(window.transparent_border());
+    window.set_pos(area, None);
+
+    Ok(())
+}
+
+/// Every zone of `grid` as `#{ row, column, rect }`, counted from 0
+fn zones(grid: Grid) -> Array {
+    let mut zones = Array::new();
+
+    for row in 0..grid.rows() {
+        for column in 0..grid.columns() {
+            if let Some(area) = grid.span_area((row, column), (row, column)) {
+                let mut zone = Map::new();
+                zone.insert("row".into(), (row as INT).into());
+                zone.insert("column".into(), (column as INT).into());
+                zone.insert("rect".into(), to_dynamic(&area));
+                zones.push(zone.into());
+            }
+        }
+    }
+
+    zones
+}
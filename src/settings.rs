@@ -0,0 +1,140 @@
+use std::fs::read_to_string;
+
+use anyhow::format_err;
+use toml_edit::{DocumentMut, Item, Table, TableLike, Value};
+
+use crate::cache::write_atomic;
+use crate::config::{check_config_edit, config_path};
+use crate::Result;
+
+/// Sets the dotted key `path`, e.g. `grid.tile_width` or `profiles.0.margins`, to
+/// `value`. The value is read as TOML, falling back to a plain string, so both
+/// `10` and `CTRL+ALT+S` work. Missing tables are created.
+pub fn set(path: &str, value: &str) -> Result<()> {
+    let mut new_value = value
+        .parse::<Value>()
+        .unwrap_or_else(|_| Value::from(value));
+    new_value.decor_mut().clear();
+
+    edit(path, true, |parent, key| {
+        match parent.get_mut(key) {
+            Some(Item::Value(existing)) => {
+                // Keep the spacing and any trailing comment of the old value
+                let decor = existing.decor().clone();
+                *existing = new_value;
+                *existing.decor_mut() = decor;
+            }
+            Some(Item::None) | None => {
+                parent.insert(key, Item::Value(new_value));
+            }
+            Some(_) => return Err(format_err!("`{}` is a table, set its keys instead", path)),
+        }
+
+        Ok(())
+    })
+}
+
+/// Removes the dotted key `path`, so its default is used. Does nothing if it
+/// isn't set.
+pub fn unset(path: &str) -> Result<()> {
+    edit(path, false, |parent, key| {
+        parent.remove(key);
+        Ok(())
+    })
+}
+
+/// Flips the boolean at the dotted key `path`, returning its new value. A missing
+/// key counts as `false`.
+pub fn toggle(path: &str) -> Result<bool> {
+    let mut enabled = false;
+
+    edit(path, true, |parent, key| {
+        match parent.get_mut(key) {
+            Some(Item::Value(Value::Boolean(existing))) => {
+                enabled = !*existing.value();
+                let decor = existing.decor().clone();
+                *existing = toml_edit::Formatted::new(enabled);
+                *existing.decor_mut() = decor;
+            }
+            Some(Item::None) | None => {
+                enabled = true;
+                parent.insert(key, toml_edit::value(enabled));
+            }
+            Some(_) => return Err(format_err!("`{}` isn't true or false", path)),
+        }
+
+        Ok(())
+    })?;
+
+    Ok(enabled)
+}
+
+/// Applies `change` to the table holding the last key of `path`, then validates
+/// the result and writes it back. The file is left untouched if the change would
+/// make the config invalid, or if that table is missing and `create` is false.
+fn edit<F>(path: &str, create: bool, change: F) -> Result<()>
+where
+    F: FnOnce(&mut dyn TableLike, &str) -> Result<()>,
+{
+    let segments = path.split('.').map(str::trim).collect::<Vec<_>>();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return Err(format_err!("Invalid key: `{}`", path));
+    }
+
+    let config_path = config_path()?;
+    let mut document = read_to_string(&config_path)?.parse::<DocumentMut>()?;
+
+    let (key, parents) = segments.split_last().unwrap();
+    match parent_table(document.as_table_mut(), parents, path, create)? {
+        Some(parent) => change(parent, key)?,
+        None => return Ok(()),
+    }
+
+    let contents = document.to_string();
+
+    check_config_edit(&contents)?;
+
+    write_atomic(&config_path, &contents)
+}
+
+/// Walks `segments` down from `table`, creating missing tables if `create` is set.
+/// Segments following an array of tables, such as `profiles`, select an entry by
+/// index.
+fn parent_table<'a>(
+    table: &'a mut dyn TableLike,
+    segments: &[&str],
+    path: &str,
+    create: bool,
+) -> Result<Option<&'a mut dyn TableLike>> {
+    let (segment, rest) = match segments.split_first() {
+        Some(split) => split,
+        None => return Ok(Some(table)),
+    };
+
+    if table.get(segment).is_none_or(Item::is_none) {
+        if !create {
+            return Ok(None);
+        }
+        table.insert(segment, Item::Table(Table::new()));
+    }
+
+    match table.get_mut(segment).unwrap() {
+        Item::ArrayOfTables(array) => {
+            let (index, rest) = rest
+                .split_first()
+                .ok_or_else(|| format_err!("`{}` is a list, give an index after it", segment))?;
+
+            let entry = index
+                .parse::<usize>()
+                .ok()
+                .and_then(move |index| array.get_mut(index))
+                .ok_or_else(|| format_err!("No entry {} in `{}`", index, segment))?;
+
+            parent_table(entry, rest, path, create)
+        }
+        item => match item.as_table_like_mut() {
+            Some(child) => parent_table(child, rest, path, create),
+            None => Err(format_err!("`{}` in `{}` isn't a table", segment, path)),
+        },
+    }
+}
@@ -1,7 +1,8 @@
 use std::mem;
+use std::path::Path;
 use std::thread;
 use windows::{
-    core::{w, PCWSTR, PWSTR},
+    core::{w, Error, PCWSTR, PWSTR},
     Win32::{
         Foundation::{HWND, LPARAM, LRESULT, POINT, WPARAM},
         Graphics::Gdi::HBITMAP,
@@ -9,36 +10,51 @@ use windows::{
         UI::{
             Input::KeyboardAndMouse::SetFocus,
             Shell::{
-                ShellExecuteW, Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD,
-                NIM_DELETE, NOTIFYICONDATAW,
+                ShellExecuteW, Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_MESSAGE, NIF_TIP,
+                NIIF_WARNING, NIM_ADD, NIM_DELETE, NIM_MODIFY, NOTIFYICONDATAW,
             },
             WindowsAndMessaging::{
-                CheckMenuItem, CreateIconFromResourceEx, CreatePopupMenu, CreateWindowExW,
-                DefWindowProcW, DestroyMenu, DispatchMessageW, GetCursorPos, GetMessageW,
-                InsertMenuW, MessageBoxW, PostMessageW, PostQuitMessage, RegisterClassExW,
-                SendMessageW, SetForegroundWindow, SetMenuDefaultItem, SetMenuItemBitmaps,
-                TrackPopupMenu, TranslateMessage, HMENU, LR_DEFAULTCOLOR, MB_ICONINFORMATION,
-                MB_OK, MF_BYPOSITION, MF_CHECKED, MF_STRING, MF_UNCHECKED, SW_SHOW, TPM_LEFTALIGN,
-                TPM_NONOTIFY, TPM_RETURNCMD, TPM_RIGHTBUTTON, WINDOW_STYLE, WM_APP, WM_CLOSE,
-                WM_COMMAND, WM_CREATE, WM_INITMENUPOPUP, WM_LBUTTONDBLCLK, WM_RBUTTONUP,
-                WNDCLASSEXW, WS_EX_NOACTIVATE,
+                AppendMenuW, CheckMenuItem, CreateIconFromResourceEx, CreatePopupMenu,
+                CreateWindowExW, DefWindowProcW, DestroyMenu, DispatchMessageW, FindWindowW,
+                GetCursorPos, GetMessageW, InsertMenuW, MessageBoxW, PostMessageW, PostQuitMessage,
+                RegisterClassExW, SendMessageW, SetForegroundWindow, SetMenuDefaultItem,
+                SetMenuItemBitmaps, TrackPopupMenu, TranslateMessage, HMENU, LR_DEFAULTCOLOR,
+                MB_ICONINFORMATION, MB_OK, MF_BYPOSITION, MF_CHECKED, MF_POPUP, MF_STRING,
+                MF_UNCHECKED, SW_SHOW, TPM_LEFTALIGN, TPM_NONOTIFY, TPM_RETURNCMD, TPM_RIGHTBUTTON,
+                WINDOW_STYLE, WM_APP, WM_CLOSE, WM_COMMAND, WM_CREATE, WM_DISPLAYCHANGE,
+                WM_INITMENUPOPUP, WM_LBUTTONDBLCLK, WM_RBUTTONUP, WNDCLASSEXW, WS_EX_NOACTIVATE,
             },
         },
     },
 };
 
 use crate::autostart;
+use crate::cache;
 use crate::common::{show_msg_box, LOWORD};
 use crate::config;
+use crate::hotkey::hotkey_error_report;
+use crate::logging;
+use crate::settings;
 use crate::str_to_wide;
 use crate::Message;
 use crate::CHANNEL;
-use crate::CONFIG;
+use crate::{ACTIVE_PROFILE, CONFIG, HOTKEY_ERRORS};
 
 const ID_ABOUT: u16 = 2000;
 const ID_EXIT: u16 = 2001;
 const ID_CONFIG: u16 = 2002;
 const ID_AUTOSTART: u16 = 2003;
+const ID_CACHE_LIST: u16 = 2004;
+const ID_CACHE_RESET: u16 = 2005;
+const ID_CACHE_PRUNE: u16 = 2006;
+const ID_CACHE_EXPORT: u16 = 2007;
+const ID_CACHE_IMPORT: u16 = 2008;
+const ID_HOTKEY_ERRORS: u16 = 2009;
+const ID_LOG: u16 = 2010;
+/// Cached grids not used for this many days are removed by the tray prune command
+const CACHE_PRUNE_DAYS: u64 = 30;
+/// Profiles submenu items are assigned ids starting from this value
+const ID_PROFILE: u16 = 3000;
 static mut MODAL_SHOWN: bool = false;
 
 pub unsafe fn spawn_sys_tray() {
@@ -85,12 +101,6 @@ unsafe fn add_icon(hwnd: HWND) {
         CreateIconFromResourceEx(icon_bytes, true, 0x0003_0000, 32, 32, LR_DEFAULTCOLOR)
             .expect("failed CreateIconFromResourceEx");
 
-    let mut tooltip_array = [0u16; 128];
-    let tooltip = "Grout";
-    let mut tooltip = tooltip.encode_utf16().collect::<Vec<_>>();
-    tooltip.extend(vec![0; 128 - tooltip.len()]);
-    tooltip_array.swap_with_slice(&mut tooltip[..]);
-
     let mut icon_data: NOTIFYICONDATAW = mem::zeroed();
     icon_data.cbSize = mem::size_of::<NOTIFYICONDATAW>() as u32;
     icon_data.hWnd = hwnd;
@@ -98,9 +108,107 @@ unsafe fn add_icon(hwnd: HWND) {
     icon_data.uCallbackMessage = WM_APP;
     icon_data.uFlags = NIF_ICON | NIF_MESSAGE | NIF_TIP;
     icon_data.hIcon = icon_handle;
-    icon_data.szTip = tooltip_array;
+    icon_data.szTip = tooltip();
+
+    if !Shell_NotifyIconW(NIM_ADD, &icon_data).as_bool() {
+        logging::log_win32_error("Shell_NotifyIconW", hwnd, &Error::from_win32());
+    }
+
+    show_hotkey_errors(hwnd);
+}
+
+fn tooltip() -> [u16; 128] {
+    let profile = ACTIVE_PROFILE.lock().unwrap().clone();
+    let profile_name = CONFIG
+        .lock()
+        .unwrap()
+        .profile(&profile)
+        .map(|profile| profile.display_name().to_owned())
+        .unwrap_or(profile);
+
+    let hotkey_errors = HOTKEY_ERRORS.lock().unwrap().len();
+    let tooltip = if hotkey_errors > 0 {
+        format!(
+            "Grout - {} ({} hotkeys disabled)",
+            profile_name, hotkey_errors
+        )
+    } else {
+        format!("Grout - {}", profile_name)
+    };
+
+    wide_array(&tooltip)
+}
+
+/// Null terminated, truncated copy of `text` for the fixed size notify icon fields
+fn wide_array<const N: usize>(text: &str) -> [u16; N] {
+    let mut array = [0u16; N];
+    for (dst, src) in array.iter_mut().zip(text.encode_utf16().take(N - 1)) {
+        *dst = src;
+    }
+    array
+}
+
+/// Shows a balloon notification if any hotkeys failed to register
+pub fn notify_hotkey_errors() {
+    unsafe {
+        let hwnd = FindWindowW(w!("Grout Tray"), PCWSTR::null());
+        if hwnd != HWND::default() {
+            show_hotkey_errors(hwnd);
+        }
+    }
+}
+
+unsafe fn show_hotkey_errors(hwnd: HWND) {
+    let hotkey_errors = HOTKEY_ERRORS.lock().unwrap().len();
+    if hotkey_errors == 0 {
+        return;
+    }
 
-    let _ = Shell_NotifyIconW(NIM_ADD, &icon_data);
+    let mut icon_data: NOTIFYICONDATAW = mem::zeroed();
+    icon_data.cbSize = mem::size_of::<NOTIFYICONDATAW>() as u32;
+    icon_data.hWnd = hwnd;
+    icon_data.uID = 1;
+    icon_data.uFlags = NIF_INFO;
+    icon_data.dwInfoFlags = NIIF_WARNING;
+    icon_data.szInfoTitle = wide_array("Grout");
+    icon_data.szInfo = wide_array(&format!(
+        "{} hotkeys could not be registered. See Hotkey Errors in the tray menu.",
+        hotkey_errors
+    ));
+
+    let _ = Shell_NotifyIconW(NIM_MODIFY, &icon_data);
+}
+
+/// Closes the tray window, which removes its icon before the program exits
+pub fn quit() {
+    unsafe {
+        let hwnd = FindWindowW(w!("Grout Tray"), PCWSTR::null());
+        if hwnd == HWND::default() {
+            let _ = CHANNEL.0.send(Message::Exit);
+            return;
+        }
+
+        let _ = PostMessageW(hwnd, WM_CLOSE, WPARAM::default(), LPARAM::default());
+    }
+}
+
+/// Refreshes the tray tooltip after the active profile changes
+pub fn update_tray_tooltip() {
+    unsafe {
+        let hwnd = FindWindowW(w!("Grout Tray"), PCWSTR::null());
+        if hwnd == HWND::default() {
+            return;
+        }
+
+        let mut icon_data: NOTIFYICONDATAW = mem::zeroed();
+        icon_data.cbSize = mem::size_of::<NOTIFYICONDATAW>() as u32;
+        icon_data.hWnd = hwnd;
+        icon_data.uID = 1;
+        icon_data.uFlags = NIF_TIP;
+        icon_data.szTip = tooltip();
+
+        let _ = Shell_NotifyIconW(NIM_MODIFY, &icon_data);
+    }
 }
 
 unsafe fn remove_icon(hwnd: HWND) {
@@ -121,6 +229,10 @@ unsafe fn show_popup_menu(hwnd: HWND) {
     let about = w!("About...");
     let auto_start = w!("Launch at startup");
     let open_config = w!("Open Config");
+    let open_log = w!("Open Log");
+    let profiles = w!("Profiles");
+    let grid_cache = w!("Grid Cache");
+    let hotkey_errors = w!("Hotkey Errors...");
     let exit = w!("Exit");
 
     let _ = InsertMenuW(menu, 0, MF_BYPOSITION | MF_STRING, ID_ABOUT as usize, about);
@@ -157,7 +269,54 @@ unsafe fn show_popup_menu(hwnd: HWND) {
         open_config,
     );
 
-    let _ = InsertMenuW(menu, 3, MF_BYPOSITION | MF_STRING, ID_EXIT as usize, exit);
+    let _ = InsertMenuW(
+        menu,
+        3,
+        MF_BYPOSITION | MF_STRING,
+        ID_LOG as usize,
+        open_log,
+    );
+
+    let profiles_menu = profiles_menu();
+
+    let _ = InsertMenuW(
+        menu,
+        4,
+        MF_BYPOSITION | MF_POPUP,
+        profiles_menu.0 as usize,
+        profiles,
+    );
+
+    let cache_menu = cache_menu();
+
+    let _ = InsertMenuW(
+        menu,
+        5,
+        MF_BYPOSITION | MF_POPUP,
+        cache_menu.0 as usize,
+        grid_cache,
+    );
+
+    let mut position = 6;
+
+    if !HOTKEY_ERRORS.lock().unwrap().is_empty() {
+        let _ = InsertMenuW(
+            menu,
+            position,
+            MF_BYPOSITION | MF_STRING,
+            ID_HOTKEY_ERRORS as usize,
+            hotkey_errors,
+        );
+        position += 1;
+    }
+
+    let _ = InsertMenuW(
+        menu,
+        position,
+        MF_BYPOSITION | MF_STRING,
+        ID_EXIT as usize,
+        exit,
+    );
 
     let _ = SetMenuDefaultItem(menu, ID_ABOUT as u32, 0);
     SetFocus(hwnd);
@@ -186,6 +345,93 @@ unsafe fn show_popup_menu(hwnd: HWND) {
     let _ = DestroyMenu(menu);
 }
 
+unsafe fn profiles_menu() -> HMENU {
+    let menu = CreatePopupMenu().expect("failed CreatePopupMenu");
+
+    let active_profile = ACTIVE_PROFILE.lock().unwrap().clone();
+    let profiles = CONFIG.lock().unwrap().profiles();
+
+    for (idx, profile) in profiles.iter().enumerate() {
+        let checked = if profile.name == active_profile {
+            MF_CHECKED
+        } else {
+            MF_UNCHECKED
+        };
+
+        let mut name = str_to_wide!(profile.display_name());
+        let _ = AppendMenuW(
+            menu,
+            MF_STRING | checked,
+            ID_PROFILE as usize + idx,
+            PCWSTR(name.as_mut_ptr()),
+        );
+    }
+
+    menu
+}
+
+unsafe fn cache_menu() -> HMENU {
+    let menu = CreatePopupMenu().expect("failed CreatePopupMenu");
+
+    let prune = format!("Remove Unused for {} Days", CACHE_PRUNE_DAYS);
+    let mut prune = str_to_wide!(prune);
+
+    let _ = AppendMenuW(
+        menu,
+        MF_STRING,
+        ID_CACHE_LIST as usize,
+        w!("Show Entries..."),
+    );
+    let _ = AppendMenuW(
+        menu,
+        MF_STRING,
+        ID_CACHE_RESET as usize,
+        w!("Reset Monitor Under Cursor"),
+    );
+    let _ = AppendMenuW(
+        menu,
+        MF_STRING,
+        ID_CACHE_PRUNE as usize,
+        PCWSTR(prune.as_mut_ptr()),
+    );
+    let _ = AppendMenuW(menu, MF_STRING, ID_CACHE_EXPORT as usize, w!("Export"));
+    let _ = AppendMenuW(menu, MF_STRING, ID_CACHE_IMPORT as usize, w!("Import"));
+
+    menu
+}
+
+unsafe fn run_cache_command(id: u16) {
+    match id {
+        ID_CACHE_LIST => show_msg_box(&cache::list_grid_cache()),
+        ID_CACHE_RESET => {
+            if cache::reset_active_grid_cache_entry(&CONFIG.lock().unwrap()) {
+                show_msg_box("Removed cached grid for the monitor under the cursor.");
+            } else {
+                show_msg_box("No cached grid for the monitor under the cursor.");
+            }
+        }
+        ID_CACHE_PRUNE => {
+            let removed = cache::prune_grid_cache(CACHE_PRUNE_DAYS);
+            show_msg_box(&format!("Removed {} cached grids.", removed));
+        }
+        ID_CACHE_EXPORT => {
+            match cache::default_export_path()
+                .and_then(|path| cache::export_grid_cache(&path).map(|_| path))
+            {
+                Ok(path) => show_msg_box(&format!("Exported grid cache to {}", path.display())),
+                Err(e) => show_msg_box(&format!("Failed to export grid cache.\n\nErr: {}", e)),
+            }
+        }
+        ID_CACHE_IMPORT => {
+            match cache::default_export_path().and_then(|path| cache::import_grid_cache(&path)) {
+                Ok(count) => show_msg_box(&format!("Imported {} cached grids.", count)),
+                Err(e) => show_msg_box(&format!("Failed to import grid cache.\n\nErr: {}", e)),
+            }
+        }
+        _ => {}
+    }
+}
+
 unsafe fn show_about() {
     let title = w!("About");
 
@@ -205,6 +451,43 @@ unsafe fn show_about() {
     );
 }
 
+/// Opens the config file in the program associated with it
+pub unsafe fn open_config(hwnd: HWND) {
+    if let Ok(config_path) = config::config_path() {
+        open_file(hwnd, &config_path);
+    }
+}
+
+/// Opens `path` in the program associated with it, if it exists
+unsafe fn open_file(hwnd: HWND, path: &Path) {
+    if !path.exists() {
+        return;
+    }
+
+    let operation = w!("open");
+    let mut path_wide = str_to_wide!(path.to_str().unwrap());
+    let path_pwstr = PWSTR(path_wide.as_mut_ptr());
+
+    let result = ShellExecuteW(
+        hwnd,
+        operation,
+        path_pwstr,
+        PCWSTR::null(),
+        PCWSTR::null(),
+        SW_SHOW,
+    );
+
+    // Values above 32 mean success
+    if result.0 <= 32 {
+        log::warn!(
+            path = path.to_string_lossy().as_ref();
+            "ShellExecuteW failed to open {}: {}",
+            path.display(),
+            result.0
+        );
+    }
+}
+
 unsafe extern "system" fn callback(
     hWnd: HWND,
     Msg: u32,
@@ -221,6 +504,9 @@ unsafe extern "system" fn callback(
             PostQuitMessage(0);
             let _ = &CHANNEL.0.clone().send(Message::Exit);
         }
+        WM_DISPLAYCHANGE => {
+            let _ = &CHANNEL.0.clone().send(Message::DisplayChange);
+        }
         WM_COMMAND => {
             if MODAL_SHOWN {
                 return LRESULT(1);
@@ -235,7 +521,7 @@ unsafe extern "system" fn callback(
                     MODAL_SHOWN = false;
                 }
                 ID_AUTOSTART => {
-                    if let Err(e) = config::toggle_autostart() {
+                    if let Err(e) = settings::toggle("auto_start") {
                         show_msg_box(&format!(
                             "Error while toggling autostart from system tray.\n\nErr: {}",
                             e
@@ -255,30 +541,39 @@ unsafe extern "system" fn callback(
                         ))
                     };
                 }
-                ID_CONFIG => {
-                    if let Some(mut config_path) = dirs::config_dir() {
-                        config_path.push("grout");
-                        config_path.push("config.toml");
-
-                        if config_path.exists() {
-                            let operation = w!("open");
-                            let mut config_path = str_to_wide!(config_path.to_str().unwrap());
-                            let config_path_pwstr = PWSTR(config_path.as_mut_ptr());
-
-                            ShellExecuteW(
-                                hWnd,
-                                operation,
-                                config_path_pwstr,
-                                PCWSTR::null(),
-                                PCWSTR::null(),
-                                SW_SHOW,
-                            );
-                        }
-                    }
-                }
+                ID_CONFIG => open_config(hWnd),
+                ID_LOG => match logging::log_path() {
+                    Ok(log_path) => open_file(hWnd, &log_path),
+                    Err(e) => log::warn!("Failed to find the log file: {}", e),
+                },
                 ID_EXIT => {
                     let _ = PostMessageW(hWnd, WM_CLOSE, WPARAM::default(), LPARAM::default());
                 }
+                ID_HOTKEY_ERRORS => {
+                    MODAL_SHOWN = true;
+
+                    let report = hotkey_error_report(&HOTKEY_ERRORS.lock().unwrap());
+                    show_msg_box(&report);
+
+                    MODAL_SHOWN = false;
+                }
+                ID_CACHE_LIST..=ID_CACHE_IMPORT => {
+                    MODAL_SHOWN = true;
+
+                    run_cache_command(LOWORD(wParam.0));
+
+                    MODAL_SHOWN = false;
+                }
+                id if id >= ID_PROFILE => {
+                    let profiles = CONFIG.lock().unwrap().profiles();
+
+                    if let Some(profile) = profiles.get((id - ID_PROFILE) as usize) {
+                        let _ = &CHANNEL
+                            .0
+                            .clone()
+                            .send(Message::ProfileChange(profile.name.clone()));
+                    }
+                }
                 _ => {}
             }
 
@@ -0,0 +1,726 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use toml_edit::{ImDocument, Item, TableLike};
+
+use crate::bus::EVENT_NAMES;
+use crate::hotkey::{parse_hotkey, parse_key_combination, parse_modifier};
+
+/// A problem found in a config file
+#[derive(Debug, Clone)]
+pub struct ConfigIssue {
+    pub file: PathBuf,
+    /// Line and column are counted from 1
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let file_name = self.file.file_name().unwrap_or_default().to_string_lossy();
+
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            file_name, self.line, self.column, self.message
+        )?;
+
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " {}", suggestion)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Bool,
+    String,
+    StringArray,
+    Integer {
+        min: i64,
+        max: i64,
+    },
+    Color,
+    Hotkey,
+    /// A key with optional modifiers
+    KeyCombination,
+    /// Key combinations separated by spaces
+    KeySequence,
+    Modifier,
+    Action,
+    Direction,
+    /// One of the events in `bus::EVENT_NAMES`
+    Event,
+    LogLevel,
+    /// Part of a monitor id or a device name, which can't be empty as it would
+    /// match every monitor
+    MonitorId,
+    Table(&'static [Field]),
+    /// Either `[[name]]` tables or an array of inline tables
+    TableArray(&'static [Field]),
+}
+
+#[derive(Clone, Copy)]
+struct Field {
+    name: &'static str,
+    kind: Kind,
+    required: bool,
+}
+
+const fn field(name: &'static str, kind: Kind) -> Field {
+    Field {
+        name,
+        kind,
+        required: false,
+    }
+}
+
+const fn required(name: &'static str, kind: Kind) -> Field {
+    Field {
+        name,
+        kind,
+        required: true,
+    }
+}
+
+const U8: Kind = Kind::Integer { min: 0, max: 255 };
+const I32: Kind = Kind::Integer {
+    min: i32::MIN as i64,
+    max: i32::MAX as i64,
+};
+const UNSIGNED: Kind = Kind::Integer {
+    min: 0,
+    max: i64::MAX,
+};
+const NON_ZERO: Kind = Kind::Integer {
+    min: 1,
+    max: u32::MAX as i64,
+};
+
+const ACTIONS: &[&str] = &[
+    "open-grid",
+    "quick-resize",
+    "maximize",
+    "focus",
+    "move-to-zone",
+    "switch-profile",
+    "save-snapshot",
+    "restore-snapshot",
+    "script",
+    "leader",
+    "exit",
+];
+
+const DIRECTIONS: &[&str] = &["left", "right", "up", "down"];
+
+const LOG_LEVELS: &[&str] = &["off", "error", "warn", "info", "debug", "trace"];
+
+const GRID_FIELDS: &[Field] = &[
+    field("tile_width", NON_ZERO),
+    field("tile_height", NON_ZERO),
+    field("margins", U8),
+    field("rows", NON_ZERO),
+    field("columns", NON_ZERO),
+];
+
+const COLOR_FIELDS: &[Field] = &[
+    field("tile", Kind::Color),
+    field("tile_hovered", Kind::Color),
+    field("tile_selected", Kind::Color),
+    field("tile_frame", Kind::Color),
+    field("grid_background", Kind::Color),
+    field("preview", Kind::Color),
+];
+
+const NAVIGATE_FIELDS: &[Field] = &[
+    field("left", Kind::Hotkey),
+    field("down", Kind::Hotkey),
+    field("up", Kind::Hotkey),
+    field("right", Kind::Hotkey),
+];
+
+const OPTIONAL_HOTKEY_FIELDS: &[Field] = &[
+    field("quick_resize", Kind::Hotkey),
+    field("maximize_toggle", Kind::Hotkey),
+    field("quick_exit", Kind::Hotkey),
+    field("navigate", Kind::Table(NAVIGATE_FIELDS)),
+];
+
+const BINDING_FIELDS: &[Field] = &[
+    required("key", Kind::Hotkey),
+    required("action", Kind::Action),
+    field("profile", Kind::String),
+    field("direction", Kind::Direction),
+    field("row", UNSIGNED),
+    field("column", UNSIGNED),
+    field("row_span", NON_ZERO),
+    field("column_span", NON_ZERO),
+    field("monitor", UNSIGNED),
+    field("name", Kind::String),
+    field("function", Kind::String),
+];
+
+const SEQUENCE_FIELDS: &[Field] = &[
+    required("key", Kind::KeySequence),
+    required("action", Kind::Action),
+    field("profile", Kind::String),
+    field("direction", Kind::Direction),
+    field("row", UNSIGNED),
+    field("column", UNSIGNED),
+    field("row_span", NON_ZERO),
+    field("column_span", NON_ZERO),
+    field("monitor", UNSIGNED),
+    field("name", Kind::String),
+    field("function", Kind::String),
+];
+
+const GRID_KEY_FIELDS: &[Field] = &[
+    field("close", Kind::KeyCombination),
+    field("add_column", Kind::KeyCombination),
+    field("remove_column", Kind::KeyCombination),
+    field("add_row", Kind::KeyCombination),
+    field("remove_row", Kind::KeyCombination),
+    field("span_modifier", Kind::Modifier),
+];
+
+const LEADER_FIELDS: &[Field] = &[
+    required("key", Kind::Hotkey),
+    field("timeout_ms", UNSIGNED),
+    field("sequences", Kind::TableArray(SEQUENCE_FIELDS)),
+];
+
+const MONITOR_FIELDS: &[Field] = &[
+    field("device", Kind::String),
+    field("width", I32),
+    field("height", I32),
+    field("x", I32),
+    field("y", I32),
+];
+
+const PROFILE_FIELDS: &[Field] = &[
+    required("name", Kind::String),
+    field("display_name", Kind::String),
+    field("key", Kind::KeyCombination),
+    field("margins", U8),
+    field("window_padding", U8),
+    field("grid", Kind::Table(GRID_FIELDS)),
+    field("colors", Kind::Table(COLOR_FIELDS)),
+    field("monitors", Kind::TableArray(MONITOR_FIELDS)),
+    field("restore_arrangement", Kind::Bool),
+    field("applications", Kind::StringArray),
+];
+
+const HOOK_FIELDS: &[Field] = &[
+    required("event", Kind::Event),
+    required("command", Kind::String),
+    field("args", Kind::StringArray),
+    field("json_arg", Kind::Bool),
+    field("timeout_ms", NON_ZERO),
+];
+
+const MONITOR_ALIAS_FIELDS: &[Field] = &[
+    required("alias", Kind::String),
+    required("id", Kind::MonitorId),
+];
+
+const CONFIG_FIELDS: &[Field] = &[
+    field("version", UNSIGNED),
+    field("include", Kind::StringArray),
+    required("margins", U8),
+    required("window_padding", U8),
+    field("hotkey", Kind::Hotkey),
+    field("optional_hotkeys", Kind::Table(OPTIONAL_HOTKEY_FIELDS)),
+    field("bindings", Kind::TableArray(BINDING_FIELDS)),
+    field("grid_keys", Kind::Table(GRID_KEY_FIELDS)),
+    field("leader", Kind::Table(LEADER_FIELDS)),
+    required("auto_start", Kind::Bool),
+    field("grid", Kind::Table(GRID_FIELDS)),
+    field("colors", Kind::Table(COLOR_FIELDS)),
+    field("profiles", Kind::TableArray(PROFILE_FIELDS)),
+    field("startup_profile", Kind::String),
+    field("profile_per_monitor", Kind::Bool),
+    field("monitor_aliases", Kind::TableArray(MONITOR_ALIAS_FIELDS)),
+    field("hooks", Kind::TableArray(HOOK_FIELDS)),
+    field("log_level", Kind::LogLevel),
+];
+
+/// Checks the files merged into the config, in the order they're merged,
+/// returning every problem found. An empty list means the config can be loaded.
+/// Required top level keys can be set in any of the files, and are reported
+/// against `root`, the main config file, when none of them sets one.
+pub fn validate_config<'a>(
+    root: &Path,
+    layers: impl IntoIterator<Item = (&'a Path, &'a str)>,
+) -> Vec<ConfigIssue> {
+    let mut issues = vec![];
+    let mut keys = HashSet::new();
+
+    for (file, contents) in layers {
+        issues.extend(validate_layer(file, contents));
+
+        if let Ok(document) = ImDocument::parse(contents) {
+            keys.extend(document.as_table().iter().map(|(key, _)| key.to_owned()));
+        }
+    }
+
+    for field in CONFIG_FIELDS.iter().filter(|field| field.required) {
+        if !keys.contains(field.name) {
+            issues.push(ConfigIssue {
+                file: root.to_path_buf(),
+                line: 1,
+                column: 1,
+                message: format!("Missing required key `{}`.", field.name),
+                suggestion: None,
+            });
+        }
+    }
+
+    issues
+}
+
+/// Checks a single config file. Required top level keys are checked across
+/// every file by `validate_config`.
+fn validate_layer(file: &Path, contents: &str) -> Vec<ConfigIssue> {
+    let mut validator = Validator {
+        file,
+        contents,
+        issues: vec![],
+    };
+
+    match ImDocument::parse(contents) {
+        Ok(document) => validator.check_table(document.as_table(), None, CONFIG_FIELDS),
+        Err(e) => validator.issue(
+            e.span(),
+            e.message().trim_end().replace('\n', ", "),
+            Some("Check the TOML syntax around this position."),
+        ),
+    }
+
+    validator.issues
+}
+
+struct Validator<'a> {
+    file: &'a Path,
+    contents: &'a str,
+    issues: Vec<ConfigIssue>,
+}
+
+impl<'a> Validator<'a> {
+    fn issue(&mut self, span: Option<Range<usize>>, message: String, suggestion: Option<&str>) {
+        let offset = span.map(|span| span.start).unwrap_or(0);
+        let (line, column) = position(self.contents, offset);
+
+        self.issues.push(ConfigIssue {
+            file: self.file.to_path_buf(),
+            line,
+            column,
+            message,
+            suggestion: suggestion.map(str::to_owned),
+        });
+    }
+
+    fn check_table(
+        &mut self,
+        table: &dyn TableLike,
+        table_span: Option<Range<usize>>,
+        fields: &[Field],
+    ) {
+        for (name, _) in table.iter() {
+            let (key, item) = match table.get_key_value(name) {
+                Some(key_value) => key_value,
+                None => continue,
+            };
+
+            match fields.iter().find(|field| field.name == name) {
+                Some(field) => self.check_item(item, key.span(), name, field.kind),
+                None => {
+                    let suggestion = closest(name, fields.iter().map(|field| field.name))
+                        .map(|closest| format!("Did you mean `{}`?", closest));
+
+                    self.issue(
+                        key.span(),
+                        format!("Unknown key `{}`.", name),
+                        suggestion.as_deref(),
+                    );
+                }
+            }
+        }
+
+        // Top level keys can be set by any of the merged files
+        let is_root = ptr::eq(fields, CONFIG_FIELDS);
+
+        for field in fields.iter().filter(|field| field.required) {
+            if !table.contains_key(field.name) && !is_root {
+                self.issue(
+                    table_span.clone(),
+                    format!("Missing required key `{}`.", field.name),
+                    None,
+                );
+            }
+        }
+
+        self.check_action_arguments(table, table_span);
+    }
+
+    /// Actions that take arguments need them set alongside the action
+    fn check_action_arguments(&mut self, table: &dyn TableLike, table_span: Option<Range<usize>>) {
+        let action = match table.get("action").and_then(Item::as_str) {
+            Some(action) => action,
+            None => return,
+        };
+
+        let arguments: &[&str] = match action {
+            "focus" => &["direction"],
+            "move-to-zone" => &["row", "column"],
+            "switch-profile" => &["profile"],
+            "save-snapshot" | "restore-snapshot" => &["name"],
+            "script" => &["function"],
+            _ => &[],
+        };
+
+        for argument in arguments {
+            if !table.contains_key(argument) {
+                self.issue(
+                    table_span.clone(),
+                    format!("Action `{}` needs `{}` to be set.", action, argument),
+                    None,
+                );
+            }
+        }
+    }
+
+    fn check_item(&mut self, item: &Item, key_span: Option<Range<usize>>, name: &str, kind: Kind) {
+        let span = item.span().or_else(|| key_span.clone());
+
+        match kind {
+            Kind::Table(fields) => match item.as_table_like() {
+                Some(table) => self.check_table(table, span, fields),
+                None => self.expected(span, name, "a table", item),
+            },
+            Kind::TableArray(fields) => {
+                if let Some(tables) = item.as_array_of_tables() {
+                    for table in tables.iter() {
+                        self.check_table(table, table.span().or_else(|| key_span.clone()), fields);
+                    }
+                } else if let Some(array) = item.as_array() {
+                    for value in array.iter() {
+                        match value.as_inline_table() {
+                            Some(table) => self.check_table(table, value.span(), fields),
+                            None => self.issue(
+                                value.span(),
+                                format!("Expected `{}` to only contain tables.", name),
+                                None,
+                            ),
+                        }
+                    }
+                } else {
+                    self.expected(span, name, "an array of tables", item);
+                }
+            }
+            Kind::StringArray => match item.as_array() {
+                Some(array) => {
+                    for value in array.iter().filter(|value| value.as_str().is_none()) {
+                        self.issue(
+                            value.span(),
+                            format!("Expected `{}` to only contain strings.", name),
+                            None,
+                        );
+                    }
+                }
+                None => self.expected(span, name, "an array of strings", item),
+            },
+            Kind::Bool => {
+                if item.as_bool().is_none() {
+                    self.expected(span, name, "true or false", item);
+                }
+            }
+            Kind::Integer { min, max } => match item.as_integer() {
+                Some(value) if value < min || value > max => self.issue(
+                    span,
+                    format!("`{}` is out of range.", name),
+                    Some(&format!("Use a value between {} and {}.", min, max)),
+                ),
+                Some(_) => {}
+                None => self.expected(span, name, "a number", item),
+            },
+            _ => match item.as_str() {
+                Some(value) => self.check_string(span, name, kind, value),
+                None => self.expected(span, name, "a string", item),
+            },
+        }
+    }
+
+    fn check_string(&mut self, span: Option<Range<usize>>, name: &str, kind: Kind, value: &str) {
+        match kind {
+            Kind::Color => {
+                if let Err(e) = csscolorparser::parse(value) {
+                    self.issue(
+                        span,
+                        format!("Invalid color `{}` for `{}`: {}.", value, name, e),
+                        Some("Use a CSS color such as \"rgb(0, 77, 128)\" or \"#004d80\"."),
+                    );
+                }
+            }
+            Kind::Hotkey => {
+                if let Err(e) = parse_hotkey(value) {
+                    self.issue(span, e.to_string(), Some("For example \"CTRL+ALT+S\"."));
+                }
+            }
+            Kind::KeyCombination => {
+                if let Err(e) = parse_key_combination(value) {
+                    self.issue(span, e.to_string(), Some("For example \"CTRL+RIGHT\"."));
+                }
+            }
+            Kind::KeySequence => {
+                let keys = value.split_whitespace().collect::<Vec<_>>();
+                if keys.is_empty() {
+                    self.issue(span.clone(), format!("`{}` is empty.", name), None);
+                }
+
+                for key in keys {
+                    if let Err(e) = parse_key_combination(key) {
+                        self.issue(
+                            span.clone(),
+                            e.to_string(),
+                            Some("Separate the keys of a sequence with spaces, e.g. \"m 1\"."),
+                        );
+                    }
+                }
+            }
+            Kind::Modifier if parse_modifier(value).is_none() => {
+                self.issue(
+                    span,
+                    format!("Unidentified modifier `{}` for `{}`.", value, name),
+                    Some("Use SHIFT, CTRL, ALT, WIN or a left / right variant such as RCTRL."),
+                );
+            }
+            Kind::MonitorId if value.trim().is_empty() => {
+                self.issue(
+                    span,
+                    format!("`{}` is empty.", name),
+                    Some("Use part of the monitor's id, such as \"DEL4105\", or its device name."),
+                );
+            }
+            Kind::Action => self.check_choice(span, name, value, ACTIONS),
+            Kind::Direction => self.check_choice(span, name, value, DIRECTIONS),
+            Kind::Event => self.check_choice(span, name, value, EVENT_NAMES),
+            Kind::LogLevel => self.check_choice(span, name, value, LOG_LEVELS),
+            _ => {}
+        }
+    }
+
+    fn check_choice(
+        &mut self,
+        span: Option<Range<usize>>,
+        name: &str,
+        value: &str,
+        choices: &[&'static str],
+    ) {
+        if choices.contains(&value) {
+            return;
+        }
+
+        let suggestion = match closest(value, choices.iter().copied()) {
+            Some(closest) => format!("Did you mean \"{}\"?", closest),
+            None => format!("Valid values are {}.", choices.join(", ")),
+        };
+
+        self.issue(
+            span,
+            format!("Unknown {} `{}`.", name, value),
+            Some(&suggestion),
+        );
+    }
+
+    fn expected(&mut self, span: Option<Range<usize>>, name: &str, expected: &str, item: &Item) {
+        self.issue(
+            span,
+            format!(
+                "Expected `{}` to be {}, found {}.",
+                name,
+                expected,
+                item.type_name()
+            ),
+            None,
+        );
+    }
+}
+
+/// Line and column of a byte offset, both counted from 1
+fn position(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .map(|line| line.chars().count())
+        .unwrap_or(0)
+        + 1;
+
+    (line, column)
+}
+
+/// Closest candidate to a misspelt `name`, if any is close enough to be a likely typo
+fn closest<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let name = name.to_lowercase();
+
+    candidates
+        .map(|candidate| (edit_distance(&name, candidate), candidate))
+        .filter(|(distance, candidate)| *distance <= 2.max(candidate.len() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUIRED: &str = "margins = 10\nwindow_padding = 5\nauto_start = false\n";
+
+    fn issues(contents: &str) -> Vec<ConfigIssue> {
+        let path = Path::new("config.toml");
+        validate_config(path, [(path, contents)])
+    }
+
+    fn only_issue(contents: &str) -> ConfigIssue {
+        let mut issues = issues(contents);
+        assert_eq!(issues.len(), 1, "{:?}", issues);
+        issues.remove(0)
+    }
+
+    #[test]
+    fn complete_config() {
+        assert!(issues(REQUIRED).is_empty());
+    }
+
+    #[test]
+    fn required_keys_from_any_file() {
+        let root = Path::new("config.toml");
+        let colors = Path::new("colors.toml");
+        let layers = [
+            (colors, "[colors]\ntile = \"#004d80\"\n"),
+            (
+                root,
+                "include = [\"colors.toml\"]\nmargins = 10\nwindow_padding = 5\n",
+            ),
+        ];
+
+        let issues = validate_config(root, layers);
+
+        assert_eq!(issues.len(), 1, "{:?}", issues);
+        assert_eq!(issues[0].file, root);
+        assert_eq!(issues[0].message, "Missing required key `auto_start`.");
+    }
+
+    #[test]
+    fn position_of_bad_value() {
+        let issue = only_issue(&format!("{}\n[grid]\nrows = \"many\"\n", REQUIRED));
+
+        assert_eq!((issue.line, issue.column), (6, 8));
+        assert_eq!(
+            issue.message,
+            "Expected `rows` to be a number, found string."
+        );
+    }
+
+    #[test]
+    fn misspelt_key() {
+        let issue = only_issue(&format!("{}profile_per_moniter = true\n", REQUIRED));
+
+        assert_eq!(issue.message, "Unknown key `profile_per_moniter`.");
+        assert_eq!(
+            issue.suggestion.as_deref(),
+            Some("Did you mean `profile_per_monitor`?")
+        );
+        assert_eq!((issue.line, issue.column), (4, 1));
+    }
+
+    #[test]
+    fn missing_required_key() {
+        let issue = only_issue("margins = 10\nwindow_padding = 5\n");
+
+        assert_eq!(issue.message, "Missing required key `auto_start`.");
+    }
+
+    #[test]
+    fn margins_out_of_range() {
+        let issue = only_issue("margins = 300\nwindow_padding = 5\nauto_start = false\n");
+
+        assert_eq!(issue.message, "`margins` is out of range.");
+        assert_eq!(
+            issue.suggestion.as_deref(),
+            Some("Use a value between 0 and 255.")
+        );
+        assert_eq!((issue.line, issue.column), (1, 11));
+    }
+
+    #[test]
+    fn unknown_action() {
+        let issue = only_issue(&format!(
+            "{}\n[[bindings]]\nkey = \"CTRL+ALT+M\"\naction = \"maximise\"\n",
+            REQUIRED
+        ));
+
+        assert_eq!(issue.message, "Unknown action `maximise`.");
+        assert_eq!(
+            issue.suggestion.as_deref(),
+            Some("Did you mean \"maximize\"?")
+        );
+    }
+
+    #[test]
+    fn impossible_hotkey() {
+        let issue = only_issue(&format!("{}hotkey = \"CTRL+ALT+NOTAKEY\"\n", REQUIRED));
+
+        assert!(
+            issue.message.contains("Unidentified key NOTAKEY"),
+            "{}",
+            issue.message
+        );
+        assert_eq!((issue.line, issue.column), (4, 10));
+    }
+
+    #[test]
+    fn profile_key_with_modifiers() {
+        let profile = |key: &str| {
+            format!(
+                "{}\n[[profiles]]\nname = \"Coding\"\nkey = \"{}\"\n",
+                REQUIRED, key
+            )
+        };
+
+        assert!(issues(&profile("CTRL+1")).is_empty());
+
+        let issue = only_issue(&profile("HYPER+1"));
+        assert!(
+            issue.message.contains("Unidentified modifier HYPER"),
+            "{}",
+            issue.message
+        );
+    }
+}
@@ -1,17 +1,23 @@
+use csscolorparser::Color;
 use std::mem;
 use windows::Win32::{
-    Foundation::HWND,
+    Foundation::{COLORREF, HWND},
+    Graphics::Gdi::{CreateSolidBrush, InvalidateRect},
     UI::WindowsAndMessaging::{
-        GetWindowInfo, GetWindowRect, SetWindowPos, ShowWindow, SWP_NOACTIVATE, SW_RESTORE,
-        WINDOWINFO, WINDOW_EX_STYLE, WINDOW_STYLE,
+        GetWindowInfo, GetWindowRect, SetLayeredWindowAttributes, SetWindowPos, ShowWindow,
+        LWA_ALPHA, SWP_NOACTIVATE, SW_RESTORE, WINDOWINFO, WINDOW_EX_STYLE, WINDOW_STYLE,
     },
 };
 
-use crate::common::Rect;
+use crate::common::{color_to_colorref, set_class_brush, Rect};
+use crate::logging;
 
 mod grid;
 pub use grid::spawn_grid_window;
 
+mod leader;
+pub use leader::spawn_leader_window;
+
 mod preview;
 pub use preview::spawn_preview_window;
 
@@ -33,7 +39,7 @@ impl Window {
 
     pub fn set_pos(&mut self, rect: Rect, insert_after: Option<Window>) {
         unsafe {
-            let _ = SetWindowPos(
+            if let Err(e) = SetWindowPos(
                 self.0,
                 insert_after.unwrap_or_default().0,
                 rect.x,
@@ -41,7 +47,9 @@ impl Window {
                 rect.width,
                 rect.height,
                 SWP_NOACTIVATE,
-            );
+            ) {
+                logging::log_win32_error("SetWindowPos", self.0, &e);
+            }
         }
     }
 
@@ -70,6 +78,17 @@ impl Window {
         (x, y)
     }
 
+    /// Replaces the background color of one of our layered windows
+    pub fn set_background(self, color: &Color) {
+        unsafe {
+            set_class_brush(self.0, CreateSolidBrush(color_to_colorref(color)));
+
+            let alpha = color.to_rgba8()[3];
+            let _ = SetLayeredWindowAttributes(self.0, COLORREF::default(), alpha, LWA_ALPHA);
+            let _ = InvalidateRect(self.0, None, true);
+        }
+    }
+
     pub fn restore(&mut self) {
         unsafe {
             let _ = ShowWindow(self.0, SW_RESTORE);
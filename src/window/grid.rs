@@ -11,25 +11,25 @@ use windows::{
         System::LibraryLoader::GetModuleHandleW,
         UI::{
             Controls::WM_MOUSELEAVE,
-            Input::KeyboardAndMouse::{
-                VIRTUAL_KEY, VK_CONTROL, VK_DOWN, VK_ESCAPE, VK_F1, VK_F2, VK_F3, VK_F4, VK_F5,
-                VK_F6, VK_LEFT, VK_RIGHT, VK_SHIFT, VK_UP,
-            },
             WindowsAndMessaging::{
                 CreateWindowExW, DefWindowProcW, DispatchMessageW, LoadCursorW, PeekMessageW,
                 RegisterClassExW, SendMessageW, SetLayeredWindowAttributes, TranslateMessage,
                 HMENU, IDC_ARROW, LWA_ALPHA, PEEK_MESSAGE_REMOVE_TYPE, WM_KEYDOWN, WM_KEYUP,
-                WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MOUSEMOVE, WM_PAINT, WNDCLASSEXW, WS_EX_LAYERED,
-                WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_POPUP,
+                WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MOUSEMOVE, WM_PAINT, WM_SYSKEYDOWN, WM_SYSKEYUP,
+                WNDCLASSEXW, WS_EX_LAYERED, WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_POPUP,
             },
         },
     },
 };
 
-use crate::common::{color_to_colorref, get_work_area, Rect, HIWORD, LOWORD};
+use crate::arrangement::record_placement;
+use crate::bus::{self, Event};
+use crate::common::{color_to_colorref, get_work_area, set_class_brush, Rect, HIWORD, LOWORD};
+use crate::hotkey::{parse_key_combination, GridCommand};
+use crate::profile::current_profile;
 use crate::window::Window;
 use crate::Message;
-use crate::{CHANNEL, GRID};
+use crate::{CHANNEL, CONFIG, GRID};
 
 pub fn spawn_grid_window(close_msg: Receiver<()>, background: Color) {
     thread::spawn(move || unsafe {
@@ -45,7 +45,8 @@ pub fn spawn_grid_window(close_msg: Receiver<()>, background: Color) {
         class.hCursor = LoadCursorW(HINSTANCE::default(), IDC_ARROW).expect("failed LoadCursorW");
 
         let alpha = background.to_rgba8()[3];
-        class.hbrBackground = CreateSolidBrush(color_to_colorref(&background));
+        let background_brush = CreateSolidBrush(color_to_colorref(&background));
+        class.hbrBackground = background_brush;
 
         RegisterClassExW(&class);
 
@@ -67,6 +68,9 @@ pub fn spawn_grid_window(close_msg: Receiver<()>, background: Color) {
             None,
         );
 
+        // Class is only registered once, so update its brush for the active profile
+        set_class_brush(hwnd, background_brush);
+
         let _ = SetLayeredWindowAttributes(hwnd, COLORREF::default(), alpha, LWA_ALPHA);
 
         let _ = &CHANNEL.0.clone().send(Message::GridWindow(Window(hwnd)));
@@ -101,84 +105,64 @@ unsafe extern "system" fn callback(
             GRID.lock().unwrap().draw(Window(hWnd));
             false
         }
-        WM_KEYDOWN => match VIRTUAL_KEY(LOWORD(wParam.0)) {
-            VK_ESCAPE => {
-                let _ = sender.send(Message::CloseWindows);
-                false
-            }
-            VK_CONTROL => {
-                GRID.lock().unwrap().control_down = true;
-                false
-            }
-            VK_SHIFT => {
-                GRID.lock().unwrap().shift_down = true;
-                false
-            }
-            VK_RIGHT => {
-                if GRID.lock().unwrap().control_down {
-                    GRID.lock().unwrap().add_column();
-                    GRID.lock().unwrap().reposition();
+        WM_KEYDOWN | WM_SYSKEYDOWN => {
+            let vk_code = LOWORD(wParam.0) as u32;
+            let mut grid = GRID.lock().unwrap();
+
+            grid.span_down = grid.keys.span_modifier_held();
+
+            match grid.keys.command(vk_code) {
+                Some(GridCommand::Close) => {
+                    let _ = sender.send(Message::CloseWindows);
                 }
-                false
-            }
-            VK_LEFT => {
-                if GRID.lock().unwrap().control_down {
-                    GRID.lock().unwrap().remove_column();
-                    GRID.lock().unwrap().reposition();
+                Some(GridCommand::AddColumn) => {
+                    grid.add_column();
+                    grid.reposition();
                 }
-                false
-            }
-            VK_UP => {
-                if GRID.lock().unwrap().control_down {
-                    GRID.lock().unwrap().add_row();
-                    GRID.lock().unwrap().reposition();
+                Some(GridCommand::RemoveColumn) => {
+                    grid.remove_column();
+                    grid.reposition();
                 }
-                false
-            }
-            VK_DOWN => {
-                if GRID.lock().unwrap().control_down {
-                    GRID.lock().unwrap().remove_row();
-                    GRID.lock().unwrap().reposition();
+                Some(GridCommand::AddRow) => {
+                    grid.add_row();
+                    grid.reposition();
                 }
-                false
-            }
-            _ => false,
-        },
-        WM_KEYUP => match VIRTUAL_KEY(LOWORD(wParam.0)) {
-            VK_CONTROL => {
-                GRID.lock().unwrap().control_down = false;
-                false
-            }
-            VK_SHIFT => {
-                GRID.lock().unwrap().shift_down = false;
-                false
-            }
-            VK_F1 => {
-                let _ = sender.send(Message::ProfileChange("Default"));
-                false
-            }
-            VK_F2 => {
-                let _ = sender.send(Message::ProfileChange("Profile2"));
-                false
-            }
-            VK_F3 => {
-                let _ = sender.send(Message::ProfileChange("Profile3"));
-                false
-            }
-            VK_F4 => {
-                let _ = sender.send(Message::ProfileChange("Profile4"));
-                false
-            }
-            VK_F5 => {
-                let _ = sender.send(Message::ProfileChange("Profile5"));
-                false
+                Some(GridCommand::RemoveRow) => {
+                    grid.remove_row();
+                    grid.reposition();
+                }
+                None => {}
             }
-            VK_F6 => {
-                let _ = sender.send(Message::ProfileChange("Profile6"));
-                false
+
+            false
+        }
+        WM_KEYUP | WM_SYSKEYUP => {
+            let vk_code = LOWORD(wParam.0) as u32;
+
+            let keys = {
+                let mut grid = GRID.lock().unwrap();
+                grid.span_down = grid.keys.span_modifier_held();
+                grid.keys.clone()
+            };
+
+            let profile = CONFIG
+                .lock()
+                .unwrap()
+                .profiles()
+                .into_iter()
+                .find(|profile| {
+                    profile
+                        .key
+                        .as_deref()
+                        .and_then(|key| parse_key_combination(key).ok())
+                        .is_some_and(|key| keys.key_pressed(&key, vk_code))
+                });
+
+            if let Some(profile) = profile {
+                let _ = sender.send(Message::ProfileChange(profile.name));
             }
-            _ => false,
-        },
+            false
+        }
         WM_MOUSEMOVE => {
             let x = LOWORD(lParam.0 as usize) as i32;
             let y = HIWORD(lParam.0 as usize) as i32;
@@ -208,6 +192,8 @@ unsafe extern "system" fn callback(
         WM_LBUTTONUP => {
             let mut grid = GRID.lock().unwrap();
 
+            let span = grid.selected_span();
+
             let repaint = if let Some(mut rect) = grid.selected_area() {
                 if let Some(mut active_window) = grid.active_window {
                     if grid.previous_resize != Some((active_window, rect)) {
@@ -217,6 +203,10 @@ unsafe extern "system" fn callback(
 
                         active_window.set_pos(rect, None);
 
+                        let profile = current_profile();
+                        record_placement(&profile, active_window, active_window.rect());
+                        bus::publish(Event::window_placed(active_window, span));
+
                         grid.previous_resize = Some((active_window, rect));
 
                         if grid.quick_resize {
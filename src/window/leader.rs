@@ -0,0 +1,283 @@
+use csscolorparser::Color;
+use std::cell::RefCell;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use windows::{
+    core::{w, PCWSTR},
+    Win32::{
+        Foundation::{COLORREF, HINSTANCE, HWND, LPARAM, LRESULT, WPARAM},
+        Graphics::Gdi::{
+            BeginPaint, CreateSolidBrush, DrawTextW, EndPaint, InvalidateRect, SetBkMode,
+            SetTextColor, DT_LEFT, DT_NOPREFIX, DT_SINGLELINE, DT_VCENTER, PAINTSTRUCT,
+            TRANSPARENT,
+        },
+        System::LibraryLoader::GetModuleHandleW,
+        UI::{
+            Input::KeyboardAndMouse::{
+                SetFocus, HOT_KEY_MODIFIERS, VIRTUAL_KEY, VK_CONTROL, VK_ESCAPE, VK_LCONTROL,
+                VK_LMENU, VK_LSHIFT, VK_LWIN, VK_MENU, VK_RCONTROL, VK_RMENU, VK_RSHIFT, VK_RWIN,
+                VK_SHIFT,
+            },
+            WindowsAndMessaging::{
+                CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, LoadCursorW,
+                PeekMessageW, RegisterClassExW, SetForegroundWindow, ShowWindow, TranslateMessage,
+                HMENU, IDC_ARROW, PEEK_MESSAGE_REMOVE_TYPE, SW_SHOW, WM_KEYDOWN, WM_KEYUP,
+                WM_KILLFOCUS, WM_PAINT, WM_SYSKEYDOWN, WM_SYSKEYUP, WNDCLASSEXW, WS_EX_TOOLWINDOW,
+                WS_EX_TOPMOST, WS_POPUP,
+            },
+        },
+    },
+};
+
+use crate::common::{color_to_colorref, get_work_area, set_class_brush, Rect, LOWORD};
+use crate::hotkey::{held_modifiers, modifiers_down, LeaderSequence};
+use crate::str_to_wide;
+use crate::Message;
+use crate::CHANNEL;
+
+const HINT_WIDTH: i32 = 280;
+const LINE_HEIGHT: i32 = 20;
+const PADDING: i32 = 8;
+
+/// Only one leader window can wait for input at a time
+static LEADER_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+struct LeaderState {
+    /// Sequences still matching the keys typed so far
+    candidates: Vec<LeaderSequence>,
+    typed: usize,
+    deadline: Instant,
+    timeout: Duration,
+    /// Modifiers of the leader key still held since the window opened, which
+    /// don't count against the keys typed until they're released
+    leader_modifiers: HOT_KEY_MODIFIERS,
+    text_color: COLORREF,
+    done: bool,
+}
+
+thread_local! {
+    static STATE: RefCell<Option<LeaderState>> = const { RefCell::new(None) };
+}
+
+/// Shows a hint of the leader sequences and grabs the keyboard until one is typed,
+/// a key matches nothing, or `timeout` passes without a key press
+pub fn spawn_leader_window(
+    sequences: Vec<LeaderSequence>,
+    timeout: Duration,
+    background: Color,
+    text_color: Color,
+) {
+    if sequences.is_empty() || LEADER_ACTIVE.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    thread::spawn(move || unsafe {
+        let hInstance = GetModuleHandleW(PCWSTR::null()).expect("failed GetModuleHandleW");
+
+        let class_name = w!("Grout Leader");
+
+        let mut class = mem::zeroed::<WNDCLASSEXW>();
+        class.cbSize = mem::size_of::<WNDCLASSEXW>() as u32;
+        class.lpfnWndProc = Some(callback);
+        class.hInstance = hInstance.into();
+        class.lpszClassName = class_name;
+        class.hCursor = LoadCursorW(HINSTANCE::default(), IDC_ARROW).expect("failed LoadCursorW");
+
+        let background_brush = CreateSolidBrush(color_to_colorref(&background));
+        class.hbrBackground = background_brush;
+
+        RegisterClassExW(&class);
+
+        let height = sequences.len() as i32 * LINE_HEIGHT + PADDING * 2;
+        let work_area = get_work_area();
+
+        STATE.with(|state| {
+            *state.borrow_mut() = Some(LeaderState {
+                candidates: sequences,
+                typed: 0,
+                deadline: Instant::now() + timeout,
+                timeout,
+                leader_modifiers: modifiers_down(),
+                text_color: color_to_colorref(&text_color),
+                done: false,
+            })
+        });
+
+        let hwnd = CreateWindowExW(
+            WS_EX_TOPMOST | WS_EX_TOOLWINDOW,
+            class_name,
+            PCWSTR::null(),
+            WS_POPUP,
+            work_area.width / 2 - HINT_WIDTH / 2 + work_area.x,
+            work_area.height / 2 - height / 2 + work_area.y,
+            HINT_WIDTH,
+            height,
+            HWND::default(),
+            HMENU::default(),
+            hInstance,
+            None,
+        );
+
+        // Class is only registered once, so update its brush for the active profile
+        set_class_brush(hwnd, background_brush);
+
+        let _ = ShowWindow(hwnd, SW_SHOW);
+        let _ = SetForegroundWindow(hwnd);
+        SetFocus(hwnd);
+
+        let mut msg = mem::zeroed();
+        loop {
+            if PeekMessageW(&mut msg, HWND::default(), 0, 0, PEEK_MESSAGE_REMOVE_TYPE(1)).into() {
+                let _ = TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            };
+
+            let finished = STATE.with(|state| match &*state.borrow() {
+                Some(state) => state.done || Instant::now() >= state.deadline,
+                None => true,
+            });
+
+            if finished {
+                break;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        STATE.with(|state| *state.borrow_mut() = None);
+        let _ = DestroyWindow(hwnd);
+
+        LEADER_ACTIVE.store(false, Ordering::SeqCst);
+    });
+}
+
+fn is_modifier(vk: VIRTUAL_KEY) -> bool {
+    [
+        VK_SHIFT,
+        VK_CONTROL,
+        VK_MENU,
+        VK_LSHIFT,
+        VK_RSHIFT,
+        VK_LCONTROL,
+        VK_RCONTROL,
+        VK_LMENU,
+        VK_RMENU,
+        VK_LWIN,
+        VK_RWIN,
+    ]
+    .contains(&vk)
+}
+
+/// Narrows the candidates to those continuing with `vk_code`, running the action
+/// once a sequence is complete
+fn key_pressed(state: &mut LeaderState, vk_code: u32) {
+    let typed = state.typed;
+    let leader_modifiers = state.leader_modifiers;
+
+    state
+        .candidates
+        .retain(|sequence| sequence.keys[typed].pressed(vk_code, leader_modifiers));
+    state.typed += 1;
+    state.deadline = Instant::now() + state.timeout;
+
+    let complete = state
+        .candidates
+        .iter()
+        .find(|sequence| sequence.keys.len() == state.typed)
+        .map(|sequence| sequence.action.clone());
+
+    if let Some(action) = complete {
+        let _ = CHANNEL.0.send(Message::Action(action));
+        state.done = true;
+    } else if state.candidates.is_empty() {
+        state.done = true;
+    }
+}
+
+unsafe fn draw(hwnd: HWND, state: &LeaderState) {
+    let mut paint: PAINTSTRUCT = mem::zeroed();
+    let hdc = BeginPaint(hwnd, &mut paint);
+
+    SetBkMode(hdc, TRANSPARENT);
+    SetTextColor(hdc, state.text_color);
+
+    for (idx, sequence) in state.candidates.iter().enumerate() {
+        let line = format!("{}    {}", sequence.label, sequence.action);
+        let mut text = str_to_wide!(line);
+        let len = text.len() - 1;
+
+        let area = Rect {
+            x: PADDING,
+            y: PADDING + idx as i32 * LINE_HEIGHT,
+            width: HINT_WIDTH - PADDING * 2,
+            height: LINE_HEIGHT,
+        };
+
+        DrawTextW(
+            hdc,
+            &mut text[..len],
+            &mut area.into(),
+            DT_LEFT | DT_VCENTER | DT_SINGLELINE | DT_NOPREFIX,
+        );
+    }
+
+    let _ = EndPaint(hwnd, &paint);
+}
+
+unsafe extern "system" fn callback(
+    hWnd: HWND,
+    Msg: u32,
+    wParam: WPARAM,
+    lParam: LPARAM,
+) -> LRESULT {
+    match Msg {
+        WM_PAINT => {
+            STATE.with(|state| {
+                if let Some(state) = &*state.borrow() {
+                    draw(hWnd, state);
+                }
+            });
+            return LRESULT(0);
+        }
+        WM_KEYDOWN | WM_SYSKEYDOWN => {
+            let vk = VIRTUAL_KEY(LOWORD(wParam.0));
+
+            if !is_modifier(vk) {
+                STATE.with(|state| {
+                    if let Some(state) = &mut *state.borrow_mut() {
+                        if vk == VK_ESCAPE {
+                            state.done = true;
+                        } else {
+                            key_pressed(state, vk.0 as u32);
+                        }
+                    }
+                });
+
+                let _ = InvalidateRect(hWnd, None, true);
+            }
+
+            return LRESULT(0);
+        }
+        WM_KEYUP | WM_SYSKEYUP => {
+            STATE.with(|state| {
+                if let Some(state) = &mut *state.borrow_mut() {
+                    state.leader_modifiers &= held_modifiers();
+                }
+            });
+
+            return LRESULT(0);
+        }
+        WM_KILLFOCUS => {
+            STATE.with(|state| {
+                if let Some(state) = &mut *state.borrow_mut() {
+                    state.done = true;
+                }
+            });
+        }
+        _ => {}
+    }
+
+    DefWindowProcW(hWnd, Msg, wParam, lParam)
+}
@@ -18,7 +18,7 @@ use windows::{
     },
 };
 
-use crate::common::color_to_colorref;
+use crate::common::{color_to_colorref, set_class_brush};
 use crate::window::Window;
 use crate::Message;
 use crate::CHANNEL;
@@ -36,7 +36,8 @@ pub fn spawn_preview_window(close_msg: Receiver<()>, preview_color: Color) {
         class.lpszClassName = class_name;
 
         let alpha = preview_color.to_rgba8()[3];
-        class.hbrBackground = CreateSolidBrush(color_to_colorref(&preview_color));
+        let preview_brush = CreateSolidBrush(color_to_colorref(&preview_color));
+        class.hbrBackground = preview_brush;
 
         RegisterClassExW(&class);
 
@@ -55,6 +56,9 @@ pub fn spawn_preview_window(close_msg: Receiver<()>, preview_color: Color) {
             None,
         );
 
+        // Class is only registered once, so update its brush for the active profile
+        set_class_brush(hwnd, preview_brush);
+
         let _ = SetLayeredWindowAttributes(hwnd, COLORREF::default(), alpha, LWA_ALPHA);
 
         let _ = &CHANNEL.0.clone().send(Message::PreviewWindow(Window(hwnd)));